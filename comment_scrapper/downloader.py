@@ -26,6 +26,7 @@ def pprint(*args, **kwargs):
     stdout_pprint(*args, stream=sys.stderr, **kwargs)
 
 YOUTUBE_VIDEO_URL = 'https://www.youtube.com/watch?v={youtube_id}'
+YOUTUBE_POST_URL = 'https://www.youtube.com/post/{post_id}'
 
 USER_AGENT = 'Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/79.0.3945.130 Safari/537.36'
 
@@ -62,7 +63,26 @@ def ajax_request(session, endpoint, ytcfg, retries=5, sleep=20):
             time.sleep(sleep)
 
 
-def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.1, *, goojf):
+def post_channel_info(data):
+    post = next(search_dict(data, 'backstagePostRenderer'), None)
+    if not post:
+        raise RuntimeError('No community post on page')
+    channel_id = post['authorEndpoint']['browseEndpoint']['browseId']
+    channel_name = ''.join(r['text'] for r in post['authorText'].get('runs', []))
+    return channel_id, channel_name
+
+
+def comment_section(data, is_post):
+    sections = list(search_dict(data, 'itemSectionRenderer'))
+    if is_post:
+        # the first section is the post itself
+        for section in sections:
+            if section.get('sectionIdentifier') == 'comment-item-section':
+                return section
+    return next(iter(sections), None)
+
+
+def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.1, *, goojf, is_post=False):
     session = requests.Session()
     session.headers['User-Agent'] = USER_AGENT
     
@@ -72,7 +92,13 @@ def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.
     session.cookies.set('goojf', goojf)
     # session.cookies.set('YSC', 'Y---')
     
-    url = YOUTUBE_VIDEO_URL.format(youtube_id=youtube_id)
+    if is_post:
+        post_id, _, lc = youtube_id.partition('&lc=')
+        url = YOUTUBE_POST_URL.format(post_id=post_id)
+        if lc:
+            url += '?lc=' + lc
+    else:
+        url = YOUTUBE_VIDEO_URL.format(youtube_id=youtube_id)
     eprint(url)
     response = session.get(url)
     
@@ -92,25 +118,30 @@ def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.
     ytcfg = json.loads(json_text)
     if not ytcfg:
         return # Unable to extract configuration
-    if language:
-        ytcfg['INNERTUBE_CONTEXT']['client']['hl'] = language
+    # badge tooltips are parsed as english by the bot
+    ytcfg['INNERTUBE_CONTEXT']['client']['hl'] = language or 'en'
 
     data = json.loads(regex_search(html, YT_INITIAL_DATA_RE, default=''))
     # print(str(data).lower().find('Ug'.lower()))
     # pprint(data)
     # with open('test_initial.json', 'w') as f:
     #     json.dump(data, f)
-    sel = Selector(text=html)
-    # chan_a = sel.css('ytd-video-owner-renderer ytd-channel-name a')
-    chan_a = sel.css('meta[itemprop="channelId"]')
-    eprint()
-    eprint('chan_a', chan_a)
-    channel_id = chan_a.attrib['content']
-    # channel_id = 'chan_temp'
-    eprint("channel_id", channel_id)
-    chan_b = sel.css('span[itemprop="author"] link[itemprop="name"]')
-    channel_name = chan_b.attrib['content']
-    eprint("channel_name", channel_name)
+    if is_post:
+        channel_id, channel_name = post_channel_info(data)
+        eprint("channel_id", channel_id)
+        eprint("channel_name", channel_name)
+    else:
+        sel = Selector(text=html)
+        # chan_a = sel.css('ytd-video-owner-renderer ytd-channel-name a')
+        chan_a = sel.css('meta[itemprop="channelId"]')
+        eprint()
+        eprint('chan_a', chan_a)
+        channel_id = chan_a.attrib['content']
+        # channel_id = 'chan_temp'
+        eprint("channel_id", channel_id)
+        chan_b = sel.css('span[itemprop="author"] link[itemprop="name"]')
+        channel_name = chan_b.attrib['content']
+        eprint("channel_name", channel_name)
     
     channel_info = {
         'channel_id': channel_id,
@@ -119,7 +150,7 @@ def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.
     channel_json = json.dumps(channel_info, ensure_ascii=False)
     print(channel_json)
 
-    section = next(search_dict(data, 'itemSectionRenderer'), None)
+    section = comment_section(data, is_post)
     renderer = next(search_dict(section, 'continuationItemRenderer'), None) if section else None
     if not renderer:
         # Comments disabled?
@@ -172,6 +203,7 @@ def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.
                 continue
             # pprint(comment)
             badge = next(search_dict(comment, 'customBadge'), False)
+            badge_label = next(search_dict(comment, 'sponsorCommentBadgeRenderer'), {}).get('tooltip', '')
             is_member = False
             if badge:
                 # eprint(badge)
@@ -188,6 +220,7 @@ def download_comments(youtube_id, sort_by=SORT_BY_RECENT, language=None, sleep=.
                 'photo': comment['authorThumbnail']['thumbnails'][-1]['url'],
                 'heart': next(search_dict(comment, 'isHearted'), False),
                 'badge': badge,
+                'badge_label': badge_label,
                 'is_member': is_member,
                 'channel_id': channel_id,
             }
@@ -216,6 +249,7 @@ def main(argv = None):
     parser = argparse.ArgumentParser(add_help=False, description=('Download Youtube comments without using the Youtube API'))
     parser.add_argument('--help', '-h', action='help', default=argparse.SUPPRESS, help='Show this help message and exit')
     parser.add_argument('--youtubeid', '-y', help='ID of Youtube video for which to download the comments')
+    parser.add_argument('--postid', '-p', help='ID of community post for which to download the comments')
     parser.add_argument('--output', '-o', help='Output filename (output format is line delimited JSON)')
     parser.add_argument('--limit', '-l', type=int, help='Limit the number of comments', default=1)
     parser.add_argument('--language', '-a', type=str, default=None, help='Language for Youtube generated text (e.g. en)')
@@ -226,20 +260,21 @@ def main(argv = None):
     # try:
     args = parser.parse_args() if argv is None else parser.parse_args(argv)
 
-    youtube_id = args.youtubeid
+    youtube_id = args.youtubeid or args.postid
+    is_post = args.postid is not None
     output = args.output
     limit = args.limit
 
     if not youtube_id:
         parser.print_usage()
-        raise ValueError('you need to specify a Youtube ID and an output filename')
+        raise ValueError('you need to specify a Youtube or post ID and an output filename')
 
     if output and os.sep in output:
         outdir = os.path.dirname(output)
         if not os.path.exists(outdir):
             os.makedirs(outdir)
 
-    eprint('Downloading Youtube comments for video:', youtube_id)
+    eprint('Downloading Youtube comments for', 'post:' if is_post else 'video:', youtube_id)
     count = 0
     # with io.open(output, 'w', encoding='utf8') as fp:
     with ExitStack() as stack:
@@ -248,7 +283,7 @@ def main(argv = None):
         sys.stderr.write('Downloaded %d comment(s)\r' % count)
         sys.stderr.flush()
         start_time = time.time()
-        for comment in download_comments(youtube_id, args.sort, args.language, goojf=args.goojf):
+        for comment in download_comments(youtube_id, args.sort, args.language, goojf=args.goojf, is_post=is_post):
             comment_json = json.dumps(comment, ensure_ascii=False)
             if output:
                 print(comment_json.decode('utf-8') if isinstance(comment_json, bytes) else comment_json, file=fp)
@@ -0,0 +1,9 @@
+alter table genteib.users
+    -- months of membership from the comment badge, 0 for new members
+    add column member_tier bigint DEFAULT NULL,
+    -- badge tooltip, e.g. "Member (6 months)"
+    add column member_badge text DEFAULT NULL;
+
+alter table genteib.servers
+    -- {role_id(string): minimum member_tier(int)}, roles not listed need tier 0
+    add column role_tiers jsonb NOT NULL DEFAULT '{}';
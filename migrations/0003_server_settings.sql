@@ -0,0 +1,17 @@
+alter table genteib.servers
+    -- how long a verification gives roles for
+    add column verify_expiry interval NOT NULL DEFAULT '3 days',
+    -- how long after the last check members are checked again by the daemon
+    add column recheck_interval interval NOT NULL DEFAULT '2 days',
+    -- users with more consecutive failed checks are skipped by the daemon
+    add column max_failed_checks bigint NOT NULL DEFAULT 5;
+
+-- strictest (smallest) settings of the servers each channel is mapped in
+create view genteib.channel_settings as
+    select
+        r.value "yt_channel_id",
+        min(s.verify_expiry) "verify_expiry",
+        min(s.recheck_interval) "recheck_interval",
+        min(s.max_failed_checks) "max_failed_checks"
+    from genteib.servers s, jsonb_each_text(s.roles) r
+    group by r.value;
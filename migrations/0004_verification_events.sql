@@ -0,0 +1,25 @@
+-- one row per update_verification run
+create table genteib.verification_events (
+    event_id bigserial NOT NULL,
+    created timestamp NOT NULL,
+    discord_id bigint NOT NULL,
+    yt_channel_id text NOT NULL,
+    yt_channel_n bigint NOT NULL,
+    yt_video_id text DEFAULT NULL,
+    yt_comment_id text DEFAULT NULL,
+    -- MembershipStatus returned by the comment source, NULL if it was not reached
+    status jsonb DEFAULT NULL,
+    comment_text text DEFAULT NULL,
+    -- channel of the comment author
+    user_yt_channel_id text DEFAULT NULL,
+    -- HumanContext messages
+    errors text[] NOT NULL DEFAULT '{}',
+    was_member boolean NOT NULL,
+    -- NULL if the run failed
+    is_member boolean DEFAULT NULL,
+    -- error returned by update_verification
+    failure text DEFAULT NULL,
+    PRIMARY KEY ("event_id")
+);
+
+create index verification_events_discord_id on genteib.verification_events (discord_id, created);
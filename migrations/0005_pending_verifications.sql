@@ -0,0 +1,12 @@
+-- users due for a re-check by the verify daemon
+-- channels not mapped in any server use the defaults from genteib.servers
+create view genteib.pending_verifications as
+    select u.discord_id, u.yt_channel_id, u.yt_channel_n
+    from genteib.users u
+    left join genteib.channel_settings cs on cs.yt_channel_id = u.yt_channel_id
+    where
+        u.yt_video_id is not null and
+        u.yt_comment_id is not null and
+        u.failed_checks <= COALESCE(cs.max_failed_checks, 5) and
+        current_timestamp - u.last_checked > COALESCE(cs.recheck_interval, INTERVAL '2 days') and
+        current_timestamp - u.last_verified > COALESCE(cs.recheck_interval, INTERVAL '2 days');
@@ -0,0 +1,7 @@
+-- channel owning each video, looked up by youtube_req::get_channel_id
+create table genteib.video_channels (
+    yt_video_id text NOT NULL,
+    yt_channel_id text NOT NULL,
+    fetched timestamp NOT NULL,
+    PRIMARY KEY (yt_video_id)
+);
@@ -0,0 +1,7 @@
+-- channel ids for @handle, /c/ and /user/ urls, see url_parse::ChannelRef::alias
+create table genteib.channel_aliases (
+    alias text NOT NULL,
+    yt_channel_id text NOT NULL,
+    fetched timestamp NOT NULL,
+    PRIMARY KEY (alias)
+);
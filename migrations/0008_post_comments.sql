@@ -0,0 +1,18 @@
+-- verification comments can be on community posts instead of videos
+alter table genteib.users
+    add column yt_post_id text DEFAULT NULL,
+    add constraint users_one_comment_target check (yt_video_id IS NULL OR yt_post_id IS NULL);
+
+alter table genteib.verification_events
+    add column yt_post_id text DEFAULT NULL;
+
+create or replace view genteib.pending_verifications as
+    select u.discord_id, u.yt_channel_id, u.yt_channel_n
+    from genteib.users u
+    left join genteib.channel_settings cs on cs.yt_channel_id = u.yt_channel_id
+    where
+        (u.yt_video_id is not null or u.yt_post_id is not null) and
+        u.yt_comment_id is not null and
+        u.failed_checks <= COALESCE(cs.max_failed_checks, 5) and
+        current_timestamp - u.last_checked > COALESCE(cs.recheck_interval, INTERVAL '2 days') and
+        current_timestamp - u.last_verified > COALESCE(cs.recheck_interval, INTERVAL '2 days');
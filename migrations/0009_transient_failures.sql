@@ -0,0 +1,20 @@
+-- scraper and network failures back off instead of counting toward failed_checks
+alter table genteib.users
+    add column transient_failures int NOT NULL DEFAULT 0,
+    add column retry_after timestamp DEFAULT NULL;
+
+-- "transient" or "definitive" when the check itself failed
+alter table genteib.verification_events
+    add column failure_kind text DEFAULT NULL;
+
+create or replace view genteib.pending_verifications as
+    select u.discord_id, u.yt_channel_id, u.yt_channel_n
+    from genteib.users u
+    left join genteib.channel_settings cs on cs.yt_channel_id = u.yt_channel_id
+    where
+        (u.yt_video_id is not null or u.yt_post_id is not null) and
+        u.yt_comment_id is not null and
+        u.failed_checks <= COALESCE(cs.max_failed_checks, 5) and
+        (u.retry_after is null or u.retry_after < current_timestamp) and
+        current_timestamp - u.last_checked > COALESCE(cs.recheck_interval, INTERVAL '2 days') and
+        current_timestamp - u.last_verified > COALESCE(cs.recheck_interval, INTERVAL '2 days');
@@ -0,0 +1,9 @@
+-- language of bot messages, see locale::resolve
+create table genteib.user_locales (
+    discord_id bigint PRIMARY KEY,
+    locale text NOT NULL
+);
+
+-- used for members without their own setting when discord sends no locale
+alter table genteib.servers
+    add column locale text DEFAULT NULL;
@@ -0,0 +1,9 @@
+-- server specific guides, one row per message of the guide
+-- servers without rows get the compiled in guide
+create table genteib.guide_sections (
+    server_id bigint NOT NULL,
+    locale text NOT NULL,
+    position int NOT NULL,
+    body text NOT NULL,
+    PRIMARY KEY (server_id, locale, position)
+);
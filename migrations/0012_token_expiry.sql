@@ -0,0 +1,4 @@
+-- tokens expire when ownership was never proven, see check.token_expiry_hours
+-- existing tokens count from when this is applied
+alter table genteib.users
+    add column token_created timestamp NOT NULL DEFAULT current_timestamp;
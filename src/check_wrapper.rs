@@ -1,43 +1,48 @@
 
 use tokio::process::Command;
 use std::process::Stdio;
+use std::collections::{ HashMap, VecDeque };
+use std::sync::{ Arc, Mutex };
+use std::fmt;
 use anyhow::Result;
 use anyhow::anyhow;
+use anyhow::Context as _;
 use lazy_static::lazy_static;
+use poise::serenity::async_trait;
+use tracing::warn;
 
 use governor::{
     RateLimiter, Quota,
-    state::{ direct:: NotKeyed, InMemoryState },
+    state::keyed::DefaultKeyedStateStore,
     clock::DefaultClock,
 };
 
-use crate::GOOJF;
+use crate::metrics;
+use crate::settings::{ self, Backend, SETTINGS };
 
 lazy_static! {
-    pub static ref RATE_LIMIT: RateLimiter<NotKeyed, InMemoryState, DefaultClock> = {
-        RateLimiter::direct(Quota::per_second(std::num::NonZeroU32::new(2).unwrap()))
-    };
-    
-    static ref CHECK_PROGRAM: String = {
-        std::env::var("check_program")
-            .unwrap_or_else(|_| "python".into())
-    };
-    
-    static ref CHECK_ARGS: Vec<String> = {
-        let raw = std::env::var("check_args")
-            .unwrap_or_else(|_| "./comment_scrapper/downloader.py".into());
-        raw.split("  ").map(|s| s.to_string()).collect()
+    /// Keyed by upstream host, so concurrent checks share one budget per host
+    pub static ref RATE_LIMIT: RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock> = {
+        let per_second = std::num::NonZeroU32::new(SETTINGS.check.requests_per_second)
+            .expect("check.requests_per_second is validated");
+        RateLimiter::keyed(Quota::per_second(per_second))
     };
 }
-// const rate_limit: RateLimiter<NotKeyed, InMemoryState, DefaultClock> =
-//     RateLimiter::direct(Quota::per_second(std::num::NonZeroU32::new(2).unwrap()));
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "status")]
 pub enum MembershipStatus {
     /// Is a member
     Member {
         channel_id: String,
         user_channel_id: String,
         text: String,
+        /// membership months from the badge, see `badge_tier`
+        #[serde(default)]
+        tier: i64,
+        /// badge tooltip
+        #[serde(default)]
+        badge: String,
     },
     /// Is not a member
     Not {
@@ -50,10 +55,188 @@ pub enum MembershipStatus {
 }
 pub use MembershipStatus::{Member, Not, NotFound};
 
+impl MembershipStatus {
+    /// Variant name for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Member{ .. } => "member",
+            Not{ .. } => "not",
+            NotFound => "not_found",
+        }
+    }
+}
+
+/// Why a check did not produce a `MembershipStatus`
+#[derive(Debug)]
+pub enum CheckError {
+    /// youtube or the scraper failed, retried with backoff without counting against the user
+    Transient(anyhow::Error),
+    /// caused by the comment the user set, like a malformed id or a removed video
+    Definitive(anyhow::Error),
+}
+
+impl CheckError {
+    /// Definitive when youtube rejects the request outright (4xx other than 429)
+    pub fn classify(err: anyhow::Error) -> Self {
+        let rejected = err.chain()
+            .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+            .filter_map(|e| e.status())
+            .any(|status| status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS);
+        if rejected {
+            CheckError::Definitive(err)
+        } else {
+            CheckError::Transient(err)
+        }
+    }
+    
+    pub fn is_transient(&self) -> bool {
+        matches!(self, CheckError::Transient(_))
+    }
+    
+    /// For metrics and verification events
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CheckError::Transient(_) => "transient",
+            CheckError::Definitive(_) => "definitive",
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Transient(err) => write!(f, "transient check failure: {:#}", err),
+            CheckError::Definitive(err) => write!(f, "check failed: {:#}", err),
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+pub type CheckResult = std::result::Result<(VideoInfo, MembershipStatus), CheckError>;
+
+/// Wait for the rate limit of the host `url` points at, recording the time spent waiting
+pub async fn wait_rate_limit(url: &str) {
+    let key = url::Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+        .unwrap_or_else(|| url.to_string());
+    
+    let start = std::time::Instant::now();
+    RATE_LIMIT.until_key_ready(&key).await;
+    metrics::RATE_LIMIT_WAIT.observe(start.elapsed().as_secs_f64());
+}
+
+/// Record latency and failures of a comment load
+async fn timed_fetch<F>(backend: &str, fut: F) -> CheckResult
+    where
+        F: std::future::Future<Output = CheckResult>
+{
+    let timer = metrics::SCRAPER_LATENCY.with_label_values(&[backend]).start_timer();
+    let res = fut.await;
+    timer.observe_duration();
+    
+    if let Err(err) = &res {
+        metrics::SCRAPER_FAILURES.with_label_values(&[backend, err.kind()]).inc();
+    }
+    res
+}
+
+/// What a verification comment was left on
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum CommentTarget {
+    Video(String),
+    /// community post
+    Post(String),
+}
+
+impl CommentTarget {
+    /// The kind is given explicitly, ids alone don't reliably tell videos and posts apart
+    pub fn new(id: String, post: bool) -> Self {
+        if post {
+            CommentTarget::Post(id)
+        } else {
+            CommentTarget::Video(id)
+        }
+    }
+    
+    /// Stored in genteib.users as either `yt_video_id` or `yt_post_id`
+    pub fn from_columns(video_id: Option<String>, post_id: Option<String>) -> Option<Self> {
+        match (video_id, post_id) {
+            (Some(video_id), _) => Some(CommentTarget::Video(video_id)),
+            (None, Some(post_id)) => Some(CommentTarget::Post(post_id)),
+            (None, None) => None,
+        }
+    }
+    
+    pub fn id(&self) -> &str {
+        match self {
+            CommentTarget::Video(id) | CommentTarget::Post(id) => id,
+        }
+    }
+    
+    pub fn video_id(&self) -> Option<&str> {
+        match self {
+            CommentTarget::Video(id) => Some(id),
+            CommentTarget::Post(_) => None,
+        }
+    }
+    
+    pub fn post_id(&self) -> Option<&str> {
+        match self {
+            CommentTarget::Video(_) => None,
+            CommentTarget::Post(id) => Some(id),
+        }
+    }
+    
+    /// Link to the comment
+    pub fn comment_url(&self, comment_id: &str) -> String {
+        match self {
+            CommentTarget::Video(id) => format!("https://www.youtube.com/watch?v={}&lc={}", id, comment_id),
+            CommentTarget::Post(id) => format!("https://www.youtube.com/post/{}?lc={}", id, comment_id),
+        }
+    }
+}
+
+impl std::fmt::Display for CommentTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommentTarget::Video(id) => write!(f, "video {}", id),
+            CommentTarget::Post(id) => write!(f, "post {}", id),
+        }
+    }
+}
+
+/// Owner of the video or post a comment was left on
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct VideoInfo {
     pub channel_name: String,
-    #[allow(dead_code)]
-    channel_id: String,
+    pub channel_id: String,
+}
+
+/// Membership months shown by a badge tooltip
+///
+/// "New member" is 0, "Member (6 months)" is 6 and "Member (2 years)" is 24.
+/// Anything unrecognised is treated as a new member.
+pub fn badge_tier(label: &str) -> i64 {
+    let label = label.to_lowercase();
+    let mut months = 0;
+    let mut n: Option<i64> = None;
+    for word in label.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        if let Ok(v) = word.parse() {
+            n = Some(v);
+            continue;
+        }
+        match (n.take(), word) {
+            (Some(v), "month" | "months") => months += v,
+            (Some(v), "year" | "years") => months += v * 12,
+            _ => (),
+        }
+    }
+    months
 }
 
 fn check_id(id: &str) -> bool {
@@ -70,6 +253,9 @@ struct ResultData {
     #[serde(rename = "channel")]
     user_channel: String,
     text: String,
+    /// tooltip of the membership badge, empty if there is none
+    #[serde(default)]
+    badge_label: String,
 }
 
 #[derive(serde::Deserialize)]
@@ -78,55 +264,271 @@ struct VideoResultData {
     channel_name: String,
 }
 
-pub async fn check_member(video_id: &str, comment_id: &str) -> Result<(VideoInfo, MembershipStatus)> {
-    RATE_LIMIT.until_ready().await;
-    
-    // let python_path = std::env::var("check_program").unwrap();
-    // let script_path = std::env::var("check_args").unwrap();
-    let python_path: &str = &CHECK_PROGRAM;
-    // let script_path = 
-    
-    if !check_id(video_id) {
-        return Err(anyhow!("invalid channel id"));
+/// Where comments and their membership badges are loaded from
+#[async_trait]
+pub trait CommentSource: Send + Sync {
+    async fn fetch(&self, target: &CommentTarget, comment_id: &str) -> CheckResult;
+}
+
+/// Picks the comment source from the `[check]` settings
+///
+/// native, subprocess to run the python scraper, or fake to serve scripted
+/// results from the json file in `fake_file`
+pub fn source_from_settings(check: &settings::Check) -> Result<Arc<dyn CommentSource>> {
+    match check.backend {
+        Backend::Native => Ok(Arc::new(NativeSource)),
+        Backend::Subprocess => Ok(Arc::new(SubprocessSource {
+            program: check.program.clone(),
+            args: check.args.clone(),
+        })),
+        Backend::Fake => {
+            let path = check.fake_file.as_ref()
+                .context("check.fake_file not set")?;
+            Ok(Arc::new(FakeSource::from_file(path)?))
+        }
+    }
+}
+
+fn check_ids(target: &CommentTarget, comment_id: &str) -> std::result::Result<(), CheckError> {
+    if !check_id(target.id()) {
+        return Err(CheckError::Definitive(anyhow!("invalid {} id", match target {
+            CommentTarget::Video(_) => "video",
+            CommentTarget::Post(_) => "post",
+        })));
         // return Ok(Not("invalid channel id"));
     }
     if !check_id(comment_id) {
-        return Err(anyhow!("invalid comment id"));
+        return Err(CheckError::Definitive(anyhow!("invalid comment id")));
         // return Ok(Not("invalid comment id"));
     }
+    Ok(())
+}
+
+/// Scrapes youtube directly, see `yt_comments`
+pub struct NativeSource;
+
+#[async_trait]
+impl CommentSource for NativeSource {
+    async fn fetch(&self, target: &CommentTarget, comment_id: &str) -> CheckResult {
+        wait_rate_limit(&target.comment_url(comment_id)).await;
+        check_ids(target, comment_id)?;
+        
+        timed_fetch("native", crate::yt_comments::fetch_comment(target, comment_id)).await
+    }
+}
+
+/// Runs comment_scrapper/downloader.py (or `program`) once per check
+pub struct SubprocessSource {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+#[async_trait]
+impl CommentSource for SubprocessSource {
+    async fn fetch(&self, target: &CommentTarget, comment_id: &str) -> CheckResult {
+        // the script loads the same pages as the native backend
+        wait_rate_limit(&target.comment_url(comment_id)).await;
+        check_ids(target, comment_id)?;
+        
+        let fut = check_member_subprocess(&self.program, &self.args, target, comment_id);
+        timed_fetch("subprocess", fut).await
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct FakeEntry {
+    #[serde(default)]
+    video_id: Option<String>,
+    #[serde(default)]
+    post_id: Option<String>,
+    comment_id: String,
+    video: VideoInfo,
+    #[serde(default)]
+    result: Option<MembershipStatus>,
+    /// fail with a transient error instead of returning `result`
+    #[serde(default)]
+    transient_error: Option<String>,
+}
+
+/// A scripted result, `Err` is returned as a transient error
+type FakeResult = std::result::Result<(VideoInfo, MembershipStatus), String>;
+
+/// Returns scripted results without touching youtube
+///
+/// Results for a comment are returned in the order they were pushed, the last
+/// one is repeated once the others are used up.
+#[derive(Default)]
+pub struct FakeSource {
+    results: Mutex<HashMap<(CommentTarget, String), VecDeque<FakeResult>>>,
+}
+
+impl FakeSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Load a json list of `{video_id or post_id, comment_id, video: {channel_id, channel_name}, result: {status, ..}}`
+    ///
+    /// `transient_error: "message"` can be given instead of `result`.
+    pub fn from_file(path: &std::path::Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("read fake results {}", path.display()))?;
+        let entries: Vec<FakeEntry> = serde_json::from_str(&raw)
+            .with_context(|| format!("parse fake results {}", path.display()))?;
+        
+        let source = Self::new();
+        for entry in entries {
+            let target = CommentTarget::from_columns(entry.video_id, entry.post_id)
+                .ok_or_else(|| anyhow!("fake result for {} has no video_id or post_id", entry.comment_id))?;
+            match (entry.result, entry.transient_error) {
+                (_, Some(message)) => source.push_transient(&target, &entry.comment_id, &message),
+                (Some(result), None) => source.push(&target, &entry.comment_id, entry.video, result),
+                (None, None) => return Err(anyhow!("fake result for {} has no result or transient_error", entry.comment_id)),
+            }
+        }
+        Ok(source)
+    }
+    
+    fn push_result(&self, target: &CommentTarget, comment_id: &str, result: FakeResult) {
+        let mut results = self.results.lock().unwrap();
+        results.entry((target.clone(), comment_id.into()))
+            .or_default()
+            .push_back(result);
+    }
+    
+    pub fn push(&self, target: &CommentTarget, comment_id: &str, video: VideoInfo, result: MembershipStatus) {
+        self.push_result(target, comment_id, Ok((video, result)));
+    }
     
-    let id_arg = format!("{}&lc={}", video_id, comment_id);
+    /// Fail the check like youtube or the scraper being down
+    pub fn push_transient(&self, target: &CommentTarget, comment_id: &str, message: &str) {
+        self.push_result(target, comment_id, Err(message.into()));
+    }
+}
+
+#[async_trait]
+impl CommentSource for FakeSource {
+    async fn fetch(&self, target: &CommentTarget, comment_id: &str) -> CheckResult {
+        let no_result = || CheckError::Definitive(anyhow!("no fake result for {} lc={}", target, comment_id));
+        
+        let mut results = self.results.lock().unwrap();
+        let queue = results.get_mut(&(target.clone(), comment_id.to_string()))
+            .ok_or_else(no_result)?;
+        
+        let res = if queue.len() > 1 {
+            queue.pop_front().unwrap()
+        } else {
+            queue.front().cloned()
+                .ok_or_else(no_result)?
+        };
+        res.map_err(|message| CheckError::Transient(anyhow!(message)))
+    }
+}
+
+/// Serves a result that was already loaded for the same comment once, then defers to `inner`
+///
+/// Used when the comment had to be loaded to find which channel a post belongs
+/// to, so the check right after does not load it again.
+pub struct Prefetched<'a> {
+    inner: &'a dyn CommentSource,
+    target: CommentTarget,
+    comment_id: String,
+    result: Mutex<Option<(VideoInfo, MembershipStatus)>>,
+}
+
+impl<'a> Prefetched<'a> {
+    pub fn new(inner: &'a dyn CommentSource, target: CommentTarget, comment_id: String, result: Option<(VideoInfo, MembershipStatus)>) -> Self {
+        Self {
+            inner,
+            target,
+            comment_id,
+            result: Mutex::new(result),
+        }
+    }
+}
+
+#[async_trait]
+impl CommentSource for Prefetched<'_> {
+    async fn fetch(&self, target: &CommentTarget, comment_id: &str) -> CheckResult {
+        if *target == self.target && comment_id == self.comment_id {
+            let prefetched = self.result.lock().unwrap().take();
+            if let Some(res) = prefetched {
+                return Ok(res);
+            }
+        }
+        self.inner.fetch(target, comment_id).await
+    }
+}
+
+/// Last lines the downloader dies with when the video or post does not exist
+const SCRAPER_UNAVAILABLE: &[&str] = &[
+    // the channelId meta is missing on pages of removed and private videos
+    "KeyError: 'content'",
+    "No community post on page",
+];
+
+/// Definitive when the downloader failed on a removed video or post, like `CheckError::classify`
+///
+/// Crashes for any other reason are not the user's fault.
+fn classify_scraper_exit(err: anyhow::Error, stderr: &str) -> CheckError {
+    let last_line = stderr.lines().rev()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("");
+    if SCRAPER_UNAVAILABLE.iter().any(|pattern| last_line.contains(pattern)) {
+        CheckError::Definitive(err.context(last_line.to_string()))
+    } else {
+        CheckError::Transient(err)
+    }
+}
+
+/// Unparsable output is transient like crashes, see `classify_scraper_exit`
+async fn check_member_subprocess(python_path: &str, args: &[String], target: &CommentTarget, comment_id: &str) -> CheckResult {
+    let id_arg = format!("{}&lc={}", target.id(), comment_id);
     
     let mut cmd = Command::new(python_path);
     // cmd.arg(script_path)
-    for arg in CHECK_ARGS.iter() {
+    for arg in args.iter() {
         cmd.arg(&*arg);
     }
+    let id_flag = match target {
+        CommentTarget::Video(_) => "--youtubeid",
+        CommentTarget::Post(_) => "--postid",
+    };
     cmd
-        .arg("--youtubeid")
+        .arg(id_flag)
         .arg(&id_arg)
         .arg("-s").arg("0")
         .arg("-l").arg("1")
-        .arg("--goojf").arg(&*GOOJF);
+        .arg("--goojf").arg(SETTINGS.youtube.goojf.expose());
     
     let child = cmd
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()?;
+        .spawn()
+        .map_err(|e| CheckError::Transient(e.into()))?;
     
-    let output = child.wait_with_output().await?;
+    let output = child.wait_with_output().await
+        .map_err(|e| CheckError::Transient(e.into()))?;
     
     if !output.status.success() {
-        return Err(anyhow!("child exited with exit status {} {}\n{:?}", output.status, id_arg, output))
+        metrics::SCRAPER_FAILURES.with_label_values(&["subprocess", "exit"]).inc();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!(status = %output.status, %stderr, "scraper failed");
+        let err = anyhow!("child exited with exit status {} {}", output.status, id_arg);
+        return Err(classify_scraper_exit(err, &stderr))
     }
     
-    let out_str = std::str::from_utf8(&output.stdout)?;
+    let out_str = std::str::from_utf8(&output.stdout)
+        .map_err(|e| CheckError::Transient(e.into()))?;
     let mut split = out_str.split('\n');
     
-    let video_info_str = split.next().ok_or_else(|| anyhow!("no channel info returned by scraper"))?;
+    let video_info_str = split.next()
+        .ok_or_else(|| CheckError::Transient(anyhow!("no channel info returned by scraper")))?;
     let comment_info_str = split.next().unwrap_or("");
     
-    let video_data: VideoResultData = serde_json::from_str(video_info_str)?;
+    let video_data: VideoResultData = serde_json::from_str(video_info_str)
+        .map_err(|e| CheckError::Transient(e.into()))?;
     
     let video_info = VideoInfo {
         channel_id: video_data.channel_id,
@@ -137,13 +539,105 @@ pub async fn check_member(video_id: &str, comment_id: &str) -> Result<(VideoInfo
         return Ok((video_info, NotFound))
     }
     
-    // dbg!(&output);
     let data: ResultData = serde_json::from_str(comment_info_str)
-        .map_err(|e| {dbg!(&output); e})?;
+        .map_err(|e| {
+            warn!(stdout = out_str, stderr = %String::from_utf8_lossy(&output.stderr), "could not parse scraper output");
+            CheckError::Transient(e.into())
+        })?;
     
     if !data.is_member {
         return Ok((video_info, Not{ channel_id: data.channel, user_channel_id: data.user_channel, text: data.text }))
     }
     
-    Ok((video_info, Member{ channel_id: data.channel, user_channel_id: data.user_channel, text: data.text }))
+    let tier = badge_tier(&data.badge_label);
+    Ok((video_info, Member{ channel_id: data.channel, user_channel_id: data.user_channel, text: data.text, tier, badge: data.badge_label }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn badge_tiers() {
+        assert_eq!(badge_tier("New member"), 0);
+        assert_eq!(badge_tier("Member (1 month)"), 1);
+        assert_eq!(badge_tier("Member (6 months)"), 6);
+        assert_eq!(badge_tier("Member (1 year)"), 12);
+        assert_eq!(badge_tier("Member (2 years)"), 24);
+        assert_eq!(badge_tier("Member (1 year, 3 months)"), 15);
+        assert_eq!(badge_tier("MEMBER (3 MONTHS)"), 3);
+        assert_eq!(badge_tier(""), 0);
+        assert_eq!(badge_tier("something else"), 0);
+    }
+    
+    fn status_error(status: u16) -> anyhow::Error {
+        let response = http::Response::builder()
+            .status(status)
+            .body("")
+            .unwrap();
+        let err = reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err();
+        anyhow::Error::from(err).context("load comment")
+    }
+    
+    #[test]
+    fn classify_scraper_exits() {
+        let removed_video = "Traceback (most recent call last):\n  ...\nKeyError: 'content'\n";
+        assert!(!classify_scraper_exit(anyhow!("exit"), removed_video).is_transient());
+        let removed_post = "Traceback (most recent call last):\nRuntimeError: No community post on page\n";
+        assert!(!classify_scraper_exit(anyhow!("exit"), removed_post).is_transient());
+        let connection = "Traceback (most recent call last):\nrequests.exceptions.ConnectionError: Max retries exceeded\n";
+        assert!(classify_scraper_exit(anyhow!("exit"), connection).is_transient());
+        assert!(classify_scraper_exit(anyhow!("exit"), "").is_transient());
+    }
+    
+    #[test]
+    fn classify_errors() {
+        assert!(!CheckError::classify(status_error(404)).is_transient());
+        assert!(!CheckError::classify(status_error(400)).is_transient());
+        assert!(CheckError::classify(status_error(429)).is_transient());
+        assert!(CheckError::classify(status_error(500)).is_transient());
+        assert!(CheckError::classify(status_error(503)).is_transient());
+        assert!(CheckError::classify(anyhow!("unable to extract ytcfg")).is_transient());
+    }
+    
+    fn video_info() -> VideoInfo {
+        VideoInfo {
+            channel_name: "channel".into(),
+            channel_id: "UCxxxxxxxxxxxxxxxxxxxxxx".into(),
+        }
+    }
+    
+    #[tokio::test]
+    async fn fake_source_repeats_last_result() {
+        let source = FakeSource::new();
+        let target = CommentTarget::Video("dQw4w9WgXcQ".into());
+        source.push(&target, "a", video_info(), NotFound);
+        source.push(&target, "a", video_info(), Not{ channel_id: "c".into(), user_channel_id: "u".into(), text: "t".into() });
+        
+        assert!(matches!(source.fetch(&target, "a").await.unwrap().1, NotFound));
+        assert!(matches!(source.fetch(&target, "a").await.unwrap().1, Not{ .. }));
+        assert!(matches!(source.fetch(&target, "a").await.unwrap().1, Not{ .. }));
+        
+        let missing = source.fetch(&target, "b").await.unwrap_err();
+        assert!(!missing.is_transient());
+        let post = CommentTarget::Post("dQw4w9WgXcQ".into());
+        assert!(source.fetch(&post, "a").await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn prefetched_is_served_once() {
+        let source = FakeSource::new();
+        let target = CommentTarget::Post("UgkxAbc".into());
+        source.push(&target, "a", video_info(), NotFound);
+        
+        let prefetched = Prefetched::new(&source, target.clone(), "a".into(), Some((video_info(), Not{
+            channel_id: "c".into(),
+            user_channel_id: "u".into(),
+            text: "t".into(),
+        })));
+        assert_eq!(prefetched.fetch(&target, "a").await.unwrap().1.kind(), "not");
+        assert_eq!(prefetched.fetch(&target, "a").await.unwrap().1.kind(), "not_found");
+    }
 }
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity::CacheAndHttp;
+use poise::serenity::http::client::Http;
+use sqlx::PgPool;
+use tracing::{ info, warn, error };
+
+use crate::check_wrapper::CommentSource;
+use crate::util::from_i;
+use crate::verification;
+use crate::roles_sync;
+use crate::shutdown::Shutdown;
+use crate::settings::{ self, SETTINGS };
+use crate::locale;
+
+/// How often each background job runs
+#[derive(Debug, Clone)]
+pub struct Intervals {
+    pub verify: Duration,
+    pub sync_roles: Duration,
+    pub over_paired: Duration,
+}
+
+impl Intervals {
+    pub fn from_settings(service: &settings::Service) -> Self {
+        Intervals {
+            verify: Duration::from_secs(service.verify_interval),
+            sync_roles: Duration::from_secs(service.sync_interval),
+            over_paired: Duration::from_secs(service.over_paired_interval),
+        }
+    }
+}
+
+pub fn cache_http(http: Arc<Http>) -> CacheAndHttp {
+    // let cache_http = poise::serenity::CacheAndHttp {
+    //     // cache: Arc::new(serenity::cache::Cache::new()),
+    //     // update_cache_timeout: None,
+    //     http: http.clone(),
+    //     ..CacheAndHttp::default(),
+    // };
+    let mut cache_http = CacheAndHttp::default();
+    cache_http.http = http;
+    cache_http
+}
+
+async fn send_message(cache_http: &CacheAndHttp, user_id: u64, msg: &str) -> Result<(), anyhow::Error> {
+    let user_id = poise::serenity::model::id::UserId(user_id);
+    let user = user_id.to_user(cache_http).await?;
+    user.direct_message(cache_http, |m| {
+        m.content(msg)
+    }).await?;
+
+    Ok(())
+}
+
+/// Check one batch of pending users, update their roles and DM them about changes
+///
+/// Roles and DMs are still sent for users checked before a shutdown request.
+pub async fn verify_batch(pool: &PgPool, source: &dyn CommentSource, cache_http: &CacheAndHttp, shutdown: &Shutdown) {
+    let ref http = cache_http.http;
+    match verification::verify_pending(pool, source, 100, SETTINGS.service.verify_concurrency, shutdown).await {
+        Ok(results) => {
+            for res in results {
+                match res.update_roles(pool, http).await {
+                    Ok(None) => (),
+                    Ok(Some(res)) => {
+                        for err in res.role_errors {
+                            warn!(error = ?err, "err updating role");
+                        }
+                    },
+                    Err(err) => {
+                        warn!(discord_id = res.discord_id, error = ?err, "err updating roles");
+                    }
+                }
+
+                // println!("{:?}", res);
+                let locale = if res.became_member() || res.became_non_member() {
+                    locale::resolve(pool, res.discord_id, None, None, None).await
+                } else {
+                    locale::DEFAULT
+                };
+                let msg = {
+                    let args: [(&str, &dyn std::fmt::Display); 2] = [("channel", &res.channel_name), ("channel_id", &res.yt_channel_id)];
+                    if res.became_member() {
+                        Some(locale.fmt("dm.now_verified", &args))
+                    } else if res.became_non_member() {
+                        let mut msg = locale.fmt("dm.no_longer_verified", &args);
+                        use std::fmt::Write;
+                        for err in res.errors.iter() {
+                            write!(msg, "\n`  `{}", err.render(locale)).unwrap();
+                        }
+                        Some(msg)
+                    } else {
+                        None
+                    }
+                };
+                if let Some(msg) = msg {
+                    match send_message(cache_http, res.discord_id, &msg).await {
+                        Ok(()) => (),
+                        Err(err) => {
+                            warn!(discord_id = res.discord_id, error = ?err, "could not send become member message");
+                        }
+                    }
+                }
+            }
+        },
+        Err(err) => error!(error = ?err, "verify pending failed"),
+    }
+}
+
+pub async fn run_verify_loop(pool: PgPool, source: Arc<dyn CommentSource>, cache_http: CacheAndHttp, interval: Duration, mut shutdown: Shutdown) {
+    while !shutdown.is_requested() {
+        verify_batch(&pool, &*source, &cache_http, &shutdown).await;
+        if !shutdown.sleep(interval).await {
+            break;
+        }
+    }
+    info!("verify loop stopped");
+}
+
+/// Sync roles for every configured server, stopping between servers on shutdown
+pub async fn sync_all_roles(pool: &PgPool, http: &Http, shutdown: &Shutdown) -> Result<(), anyhow::Error> {
+    let rows: Vec<(i64,)> = sqlx::query_as(r#"
+        SELECT server_id
+        FROM genteib.servers
+    "#)
+        .fetch_all(pool).await?;
+
+    for (server_id,) in rows {
+        if shutdown.is_requested() {
+            break;
+        }
+        let server_id = from_i(server_id);
+        info!(server_id, "syncing roles");
+        match roles_sync::sync_roles(pool, http, server_id).await {
+            Ok(()) => (),
+            Err(err) => {
+                error!(server_id, error = ?err, "sync roles error");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_sync_loop(pool: PgPool, http: Arc<Http>, interval: Duration, mut shutdown: Shutdown) {
+    while !shutdown.is_requested() {
+        if let Err(err) = sync_all_roles(&pool, &http, &shutdown).await {
+            error!(error = ?err, "sync roles error");
+        }
+        if !shutdown.sleep(interval).await {
+            break;
+        }
+    }
+    info!("role sync loop stopped");
+}
+
+pub async fn check_over_paired(pool: &PgPool) {
+    let res = verification::check_over_paired_discord_ids(pool).await;
+    match res {
+        Ok(removed) => {
+            if !removed.is_empty() {
+                info!(?removed, "removed over paired channels");
+            }
+        }
+        Err(err) => error!(error = ?err, "over paired check failed"),
+    }
+}
+
+pub async fn run_over_paired_loop(pool: PgPool, interval: Duration, mut shutdown: Shutdown) {
+    while !shutdown.is_requested() {
+        check_over_paired(&pool).await;
+        if !shutdown.sleep(interval).await {
+            break;
+        }
+    }
+    info!("over paired loop stopped");
+}
+
+/// Run the verification loop, role sync and over paired sweep next to the bot
+pub fn spawn_service_tasks(
+    pool: PgPool,
+    source: Arc<dyn CommentSource>,
+    http: Arc<Http>,
+    intervals: Intervals,
+    shutdown: Shutdown,
+) {
+    info!(?intervals, "starting service tasks");
+    tokio::spawn(run_verify_loop(pool.clone(), source, cache_http(http.clone()), intervals.verify, shutdown.clone()));
+    tokio::spawn(run_sync_loop(pool.clone(), http, intervals.sync_roles, shutdown.clone()));
+    tokio::spawn(run_over_paired_loop(pool, intervals.over_paired, shutdown));
+}
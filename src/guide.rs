@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use anyhow::Context as _;
+use poise::serenity::model::guild::Role;
+use poise::serenity::model::id::RoleId;
+use sqlx::PgPool;
+
+use crate::locale::Locale;
+use crate::roles_sync;
+use crate::settings::SETTINGS;
+use crate::util::{ to_i, from_i };
+
+/// Each section is sent as its own embed field, which discord caps at 1024 characters
+pub const MAX_SECTION_LEN: usize = 1024;
+pub const MAX_SECTIONS: usize = 10;
+
+/// Guide text split into messages on lines of `>---`
+pub fn split_sections(text: &str) -> Vec<String> {
+    text.split(">---")
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Values for the placeholders only server guides have
+pub struct ServerVars {
+    /// `{server}`
+    pub name: String,
+    /// `{channels}`, one mapped channel link per line
+    pub channels: String,
+    /// `{roles}`, one role per line with its channel and minimum tier
+    pub roles: String,
+}
+
+impl ServerVars {
+    /// `locale` is used for deleted roles and minimum tiers in `{roles}`
+    pub async fn load(pool: &PgPool, server_id: u64, name: String, guild_roles: &HashMap<RoleId, Role>, locale: Locale) -> Result<Self, anyhow::Error> {
+        let channels = roles_sync::get_channel_roles(pool, server_id).await?
+            .unwrap_or_default();
+        let deleted_role = locale.text("admin.deleted_role");
+        
+        let mut channel_list = String::new();
+        let mut role_list = String::new();
+        for (yt_channel_id, roles) in channels.iter() {
+            writeln!(channel_list, "<https://www.youtube.com/channel/{}>", yt_channel_id).unwrap();
+            for (role_id, min_tier) in roles.iter() {
+                let role_name = guild_roles.get(role_id)
+                    .map(|r| r.name.as_str())
+                    .unwrap_or(&deleted_role);
+                write!(role_list, "{} - <https://www.youtube.com/channel/{}>", role_name, yt_channel_id).unwrap();
+                if *min_tier > 0 {
+                    role_list.push_str(&locale.fmt("admin.role_min_tier", &[("months", min_tier)]));
+                }
+                role_list.push('\n');
+            }
+        }
+        
+        Ok(ServerVars {
+            name,
+            channels: channel_list.trim_end().to_string(),
+            roles: role_list.trim_end().to_string(),
+        })
+    }
+}
+
+/// Fill in `{video_id}`, `{channel_id}` and `{support_text}`, and the server placeholders if given
+pub fn fill(text: &str, server: Option<&ServerVars>) -> String {
+    let mut out = text
+        .replace("{video_id}", &SETTINGS.youtube.token_video)
+        .replace("{channel_id}", &SETTINGS.youtube.token_channel)
+        .replace("{support_text}", &SETTINGS.guide.support_text);
+    if let Some(server) = server {
+        out = out
+            .replace("{server}", &server.name)
+            .replace("{channels}", &server.channels)
+            .replace("{roles}", &server.roles);
+    }
+    out
+}
+
+/// The compiled in guide with the global placeholders filled in
+pub fn builtin(locale: Locale) -> Vec<String> {
+    split_sections(&fill(locale.guide_source(), None))
+}
+
+/// Sections of a server's own guide, unfilled
+///
+/// Uses the guide for `locale` if the server has one, otherwise any other
+/// language it has. `None` if the server has no guide.
+pub async fn get_server_guide(pool: &PgPool, server_id: u64, locale: Locale) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as(r#"
+        SELECT locale, body
+        FROM genteib.guide_sections
+        WHERE
+            server_id = $1
+        ORDER BY (locale = $2) DESC, locale, position
+    "#)
+        .bind(to_i(server_id))
+        .bind(locale.code())
+        .fetch_all(pool).await
+        .context("select guide sections")?;
+    
+    let first_locale = match rows.first() {
+        Some((first_locale, _)) => first_locale.clone(),
+        None => return Ok(None),
+    };
+    let sections = rows.into_iter()
+        .filter(|(locale, _)| *locale == first_locale)
+        .map(|(_, body)| body)
+        .collect();
+    Ok(Some(sections))
+}
+
+/// Replace the server's guide for `locale`, no sections removes it
+pub async fn set_server_guide(pool: &PgPool, server_id: u64, locale: Locale, sections: &[String]) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    
+    sqlx::query(r#"
+        DELETE FROM genteib.guide_sections
+        WHERE
+            server_id = $1 AND
+            locale = $2
+    "#)
+        .bind(to_i(server_id))
+        .bind(locale.code())
+        .execute(&mut transaction).await
+        .context("delete guide sections")?;
+    
+    for (position, body) in sections.iter().enumerate() {
+        sqlx::query(r#"
+            INSERT INTO genteib.guide_sections (server_id, locale, position, body)
+            VALUES ($1, $2, $3, $4)
+        "#)
+            .bind(to_i(server_id))
+            .bind(locale.code())
+            .bind(position as i32)
+            .bind(body)
+            .execute(&mut transaction).await
+            .context("insert guide section")?;
+    }
+    
+    transaction.commit().await.context("transaction commit")?;
+    Ok(())
+}
+
+/// Remove the server's guide for `locale`, or for every language
+pub async fn clear_server_guide(pool: &PgPool, server_id: u64, locale: Option<Locale>) -> Result<u64, anyhow::Error> {
+    let res = sqlx::query(r#"
+        DELETE FROM genteib.guide_sections
+        WHERE
+            server_id = $1 AND
+            ($2::text IS NULL OR locale = $2)
+    "#)
+        .bind(to_i(server_id))
+        .bind(locale.map(|l| l.code()))
+        .execute(pool).await
+        .context("clear guide sections")?;
+    Ok(res.rows_affected())
+}
+
+/// Servers that have a guide of their own
+pub async fn servers_with_guides(pool: &PgPool) -> Result<Vec<u64>, anyhow::Error> {
+    let rows: Vec<(i64,)> = sqlx::query_as(r#"
+        SELECT DISTINCT server_id
+        FROM genteib.guide_sections
+    "#)
+        .fetch_all(pool).await
+        .context("select guide servers")?;
+    
+    Ok(rows.into_iter().map(|(id,)| from_i(id)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn sections() {
+        assert_eq!(split_sections("one\n>---\ntwo\n>---\nthree"), vec!["one", "two", "three"]);
+        assert_eq!(split_sections("  one  \n\n>---\n\n  two\n"), vec!["one", "two"]);
+        assert_eq!(split_sections("one\n>---\n\n>---\n>---\ntwo"), vec!["one", "two"]);
+        assert_eq!(split_sections(">---\none\n>---"), vec!["one"]);
+        assert_eq!(split_sections("line\nother line"), vec!["line\nother line"]);
+        assert!(split_sections("").is_empty());
+        assert!(split_sections(" \n>---\n ").is_empty());
+    }
+    
+    #[test]
+    fn builtin_guides_fit_embeds() {
+        for locale in Locale::ALL {
+            let sections = split_sections(locale.guide_source());
+            assert!(!sections.is_empty(), "{} guide is empty", locale.code());
+            assert!(sections.len() <= MAX_SECTIONS, "{} guide has too many sections", locale.code());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt;
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::util::to_i;
+
+/// Languages the bot has a catalog and guide for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+    En,
+    Ja,
+    Id,
+}
+
+pub const DEFAULT: Locale = Locale::En;
+
+lazy_static! {
+    /// locale -> dotted key ("error.not_a_member") -> text, main loads it at startup
+    pub static ref CATALOGS: HashMap<Locale, HashMap<String, String>> = {
+        Locale::ALL.iter()
+            .map(|&locale| (locale, load_catalog(locale)))
+            .collect()
+    };
+}
+
+fn flatten(prefix: &str, table: toml::value::Table, out: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, out),
+            toml::Value::String(text) => {
+                out.insert(key, text);
+            }
+            other => panic!("locale key {} is not a string: {:?}", key, other),
+        }
+    }
+}
+
+fn load_catalog(locale: Locale) -> HashMap<String, String> {
+    let table: toml::value::Table = toml::from_str(locale.catalog_source())
+        .unwrap_or_else(|err| panic!("invalid {} catalog: {}", locale.code(), err));
+    let mut out = HashMap::new();
+    flatten("", table, &mut out);
+    out
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::En, Locale::Ja, Locale::Id];
+    
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Ja => "ja",
+            Locale::Id => "id",
+        }
+    }
+    
+    /// Only the language part of discord locales like "en-US" matters
+    pub fn from_code(code: &str) -> Option<Self> {
+        let lang = code.split(|c| c == '-' || c == '_').next().unwrap_or("");
+        Locale::ALL.iter().copied()
+            .find(|locale| locale.code().eq_ignore_ascii_case(lang))
+    }
+    
+    fn catalog_source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("locales/en.toml"),
+            Locale::Ja => include_str!("locales/ja.toml"),
+            Locale::Id => include_str!("locales/id.toml"),
+        }
+    }
+    
+    /// Guide markdown, split into messages on `>---`
+    pub fn guide_source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("guide_text.md"),
+            Locale::Ja => include_str!("guide_text.ja.md"),
+            Locale::Id => include_str!("guide_text.id.md"),
+        }
+    }
+    
+    /// Text for `key`, falling back to english
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        CATALOGS[self].get(key)
+            .or_else(|| CATALOGS[&DEFAULT].get(key))
+            .map(|text| text.as_str())
+    }
+    
+    /// Like `get`, shows the key itself if no catalog has it
+    pub fn text(&self, key: &str) -> String {
+        match self.get(key) {
+            Some(text) => text.to_string(),
+            None => {
+                warn!(locale = self.code(), key, "missing locale key");
+                key.to_string()
+            }
+        }
+    }
+    
+    /// `text` with `{name}` placeholders replaced
+    pub fn fmt(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let mut text = self.text(key);
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text("language.name"))
+    }
+}
+
+/// Locale for messages to a user
+///
+/// Their own setting wins, then the setting of the server the command was run
+/// in, then the locale discord sent with the interaction, then the preferred
+/// locale of that server in discord.
+pub async fn resolve(pool: &PgPool, user_id: u64, guild_id: Option<u64>, interaction: Option<&str>, guild_preferred: Option<&str>) -> Locale {
+    let row: Result<(Option<String>, Option<String>), _> = sqlx::query_as(r#"
+        SELECT
+            (SELECT locale FROM genteib.user_locales WHERE discord_id = $1),
+            (SELECT locale FROM genteib.servers WHERE server_id = $2)
+    "#)
+        .bind(to_i(user_id))
+        .bind(guild_id.map(to_i))
+        .fetch_one(pool).await;
+    
+    let (user, guild) = match row {
+        Ok(row) => row,
+        Err(err) => {
+            warn!(user_id, error = ?err, "could not load locale settings");
+            (None, None)
+        }
+    };
+    
+    user.as_deref().and_then(Locale::from_code)
+        .or_else(|| guild.as_deref().and_then(Locale::from_code))
+        .or_else(|| interaction.and_then(Locale::from_code))
+        .or_else(|| guild_preferred.and_then(Locale::from_code))
+        .unwrap_or(DEFAULT)
+}
+
+/// `None` clears the setting so the server or interaction locale is used again
+pub async fn set_user_locale(pool: &PgPool, user_id: u64, locale: Option<Locale>) -> Result<(), anyhow::Error> {
+    match locale {
+        Some(locale) => {
+            sqlx::query(r#"
+                INSERT INTO genteib.user_locales (discord_id, locale)
+                VALUES ($1, $2)
+                ON CONFLICT (discord_id)
+                    DO UPDATE SET locale = EXCLUDED.locale
+            "#)
+                .bind(to_i(user_id))
+                .bind(locale.code())
+                .execute(pool).await
+                .context("set user locale")?;
+        }
+        None => {
+            sqlx::query(r#"
+                DELETE FROM genteib.user_locales
+                WHERE discord_id = $1
+            "#)
+                .bind(to_i(user_id))
+                .execute(pool).await
+                .context("clear user locale")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::settings::{ self, LogFormat };
+
+/// Install the global subscriber, `level` was already checked by settings validation
+pub fn init(logging: &settings::Logging) {
+    let filter = EnvFilter::try_new(&logging.level)
+        .expect("invalid logging.level");
+    
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter);
+    
+    match logging.format {
+        LogFormat::Text => builder.init(),
+        // one json object per line, span fields included
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).init(),
+    }
+}
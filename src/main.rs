@@ -10,7 +10,6 @@
 //     }
 // };
 
-use std::fmt;
 use std::{ time::Duration };
 use anyhow::Context as _;
 
@@ -19,21 +18,28 @@ use poise::serenity_prelude as serenity;
 use poise::serenity::model::id::UserId;
 
 mod util;
-use util::{ to_i, from_i };
+use util::to_i;
 
 mod check_wrapper;
+use check_wrapper::CommentTarget;
 mod verification;
 mod roles_sync;
 mod url_parse;
 mod youtube_req;
+mod yt_comments;
+mod metrics;
+mod daemon;
+mod shutdown;
+mod settings;
+use settings::SETTINGS;
+mod logging;
+mod locale;
+use locale::Locale;
+mod guide;
+mod wizard;
 
-lazy_static::lazy_static! {
-    static ref GOOJF: String = {
-        std::env::var("goojf").expect("goojf environment variable not set")
-    };
-}
+use tracing::{ info, warn, error };
 
-const GUIDE: &str = include_str!("guide_text.md");
 
 // #[group]
 // #[commands(ping)]
@@ -71,39 +77,18 @@ const GUIDE: &str = include_str!("guide_text.md");
 //     Ok(())
 // }
 
-#[derive(Debug)]
-struct HumanError(String);
-
-impl fmt::Display for HumanError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
-    }
-}
-
 use sqlx::{ PgPool };
 
-// use crate::verification::HumanContext;
-
-#[derive(Debug)]
-enum GetPoolError {
-    MissingEnv,
-    Sqlx(sqlx::Error),
-}
+mod user_error;
+use user_error::UserError;
 
-async fn get_pool() -> Result<PgPool, GetPoolError> {
-    let pg_url = match std::env::var("pg_url") {
-        Ok(token) => token,
-        Err(_e) => {
-            // eprintln!("Failed to get env var pg_url: {:?}", e);
-            return Err(GetPoolError::MissingEnv);
-        }
-    };
-    
+async fn get_pool() -> Result<PgPool, sqlx::Error> {
     use sqlx::postgres::PgPoolOptions;
     
+    // every concurrent check holds a connection for its transaction
     let pool: PgPool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&pg_url).await.map_err(|e| GetPoolError::Sqlx(e))?;
+        .max_connections(5 + SETTINGS.service.verify_concurrency as u32)
+        .connect(SETTINGS.database.url.expose()).await?;
     
     Ok(pool)
 }
@@ -118,13 +103,43 @@ pub struct Config {
 // type Data = ();
 pub struct Data {
     pool: PgPool,
+    comment_source: std::sync::Arc<dyn check_wrapper::CommentSource>,
     config: Config,
-    guide_text: Vec<String>,
+    /// guide split into messages, per locale
+    guide_text: std::collections::HashMap<Locale, Vec<String>>,
+    /// users whose DM replies go to a running `verify` wizard
+    active_wizards: wizard::ActiveWizards,
 }
 // type Error = Box<dyn std::error::Error + Send + Sync>;
 type Error = anyhow::Error;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
+/// Locale of the author's discord client, sent with slash commands, prefix commands have none
+fn interaction_locale<'a>(ctx: Context<'a>) -> Option<&'a str> {
+    match ctx {
+        poise::Context::Application(ctx) => Some(&ctx.interaction.locale),
+        poise::Context::Prefix(_) => None,
+    }
+}
+
+/// Preferred locale discord has for the server the command was run in, from the cache
+async fn guild_preferred_locale(ctx: Context<'_>) -> Option<String> {
+    let guild_id = ctx.guild_id()?;
+    ctx.discord().cache.guild_field(guild_id, |guild| guild.preferred_locale.clone()).await
+}
+
+/// Locale to reply to the author in, see `locale::resolve`
+async fn ctx_locale(ctx: Context<'_>) -> Locale {
+    let guild_preferred = guild_preferred_locale(ctx).await;
+    locale::resolve(
+        &ctx.data().pool,
+        ctx.author().id.0,
+        ctx.guild_id().map(|id| id.0),
+        interaction_locale(ctx),
+        guild_preferred.as_deref(),
+    ).await
+}
+
 /// Register application commands in this guild or globally
 ///
 /// Run with no arguments to register in guild, run with argument "global" to register globally.
@@ -147,10 +162,74 @@ pub async fn age(
     Ok(())
 }
 
+/// Servers with their own guide that the author is a member of, with their names
+async fn guide_servers(ctx: Context<'_>) -> Result<Vec<(serenity::GuildId, String)>, Error> {
+    let mut out = Vec::new();
+    for server_id in guide::servers_with_guides(&ctx.data().pool).await? {
+        let guild_id = serenity::GuildId(server_id);
+        if guild_id.member(ctx.discord(), ctx.author().id).await.is_err() {
+            continue;
+        }
+        let guild = guild_id.to_partial_guild(ctx.discord()).await?;
+        out.push((guild_id, guild.name));
+    }
+    Ok(out)
+}
+
+/// Server whose guide to show, the `server` argument is a server id or name
+async fn guide_server(ctx: Context<'_>, server: Option<String>) -> Result<Option<serenity::GuildId>, Error> {
+    let server = match server {
+        Some(server) => server,
+        None => return Ok(ctx.guild_id()),
+    };
+    let servers = guide_servers(ctx).await?;
+    let found = servers.iter()
+        .find(|(guild_id, name)| guild_id.0.to_string() == server.trim() || name.eq_ignore_ascii_case(server.trim()));
+    match found {
+        Some((guild_id, _)) => Ok(Some(*guild_id)),
+        None => Err(anyhow::anyhow!("no guide server {}", server)
+            .context(UserError::UnknownGuideServer(server))),
+    }
+}
+
+/// Guide messages for `guide` and `dmme`
+///
+/// The server's own guide if it has one, otherwise the built in guide. Outside
+/// servers the built in guide ends with the servers the user can pick instead.
+async fn guide_messages(ctx: Context<'_>, server: Option<String>, locale: Locale) -> Result<Vec<String>, Error> {
+    let ref pool = ctx.data().pool;
+    let guild_id = guide_server(ctx, server).await?;
+    
+    if let Some(guild_id) = guild_id {
+        if let Some(sections) = guide::get_server_guide(pool, guild_id.0, locale).await? {
+            let name = guild_id.to_partial_guild(ctx.discord()).await?.name;
+            let guild_roles = guild_id.roles(ctx.discord()).await?;
+            let vars = guide::ServerVars::load(pool, guild_id.0, name, &guild_roles, locale).await?;
+            return Ok(sections.iter()
+                .map(|section| guide::fill(section, Some(&vars)))
+                .collect());
+        }
+    }
+    
+    let mut parts = ctx.data().guide_text[&locale].clone();
+    if guild_id.is_none() {
+        let servers = guide_servers(ctx).await?;
+        if !servers.is_empty() {
+            let names = servers.iter()
+                .map(|(_, name)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            parts.push(locale.fmt("reply.server_guides", &[("servers", &names)]));
+        }
+    }
+    Ok(parts)
+}
+
 /// Request a DM from the bot
 #[poise::command(prefix_command, slash_command)]
 pub async fn dmme(
     ctx: Context<'_>,
+    #[description = "Server to get the guide of, defaults to this one"] server: Option<String>,
 ) -> Result<(), Error> {
     // ctx.author().direct_message(&ctx.discord(), |m| {
     //     m
@@ -161,7 +240,10 @@ pub async fn dmme(
     // }).await?;
     
     // let parts = GUIDE.split(">---");
-    let parts = ctx.data().guide_text.iter().map(|x| x.as_str());
+    let locale = ctx_locale(ctx).await;
+    let title = locale.text("reply.guide_title");
+    let parts = guide_messages(ctx, server, locale).await?;
+    let parts = parts.iter().map(|x| x.as_str());
     
     // let part = parts.next().ok_or_else(|| anyhow::anyhow!("guide has no parts"))?;
     for part in parts {
@@ -169,7 +251,7 @@ pub async fn dmme(
             m
                 // .content("8")
                 .embed(|e| {
-                    e.field("Guide", part.trim(), true)
+                    e.field(&title, part.trim(), true)
                 })
                 
         }).await?;
@@ -182,15 +264,19 @@ pub async fn dmme(
 #[poise::command(prefix_command, slash_command)]
 pub async fn guide(
     ctx: Context<'_>,
+    #[description = "Server to get the guide of, defaults to this one"] server: Option<String>,
 ) -> Result<(), Error> {
     // let parts = GUIDE.split(">---");
-    let parts = ctx.data().guide_text.iter().map(|x| x.as_str());
+    let locale = ctx_locale(ctx).await;
+    let title = locale.text("reply.guide_title");
+    let parts = guide_messages(ctx, server, locale).await?;
+    let parts = parts.iter().map(|x| x.as_str());
     
     for part in parts {
         ctx.send(|m| {
             m
                 .embed(|e| {
-                    e.field("Guide", part.trim(), true)
+                    e.field(&title, part.trim(), true)
                 })
                 // .attachment((GUIDE.as_bytes(), "Guide.md").into())
         }).await?;
@@ -208,50 +294,109 @@ pub async fn guide(
     Ok(())
 }
 
-fn parse_channel_str(yt_channel_id: &str) -> Result<(String, i64), Error> {
-    let chan_id = if url_parse::is_url(&yt_channel_id) {
-        let yt_video_url = yt_channel_id
-            .strip_prefix("<").unwrap_or(&yt_channel_id)
-            .strip_suffix(">").unwrap_or(&yt_channel_id);
-        
-        let yt_channel_id = match url_parse::extract_channel_id(yt_video_url) {
-            Some(id) => id,
-            None => {
-                let err = anyhow::anyhow!("could not extract channel id from url {}", yt_channel_id)
-                    .context(HumanError("could not extract channel id from url".into()));
-                return Err(err)
-            }
-        };
-        yt_channel_id
-    } else {
-        yt_channel_id.to_string()
-    };
+/// Channel id and n from an id, channel url or @handle with an optional `'n` suffix
+async fn parse_channel_str(pool: &PgPool, yt_channel_id: &str) -> Result<(String, i64), Error> {
+    let yt_channel_id = yt_channel_id
+        .strip_prefix("<").unwrap_or(&yt_channel_id)
+        .strip_suffix(">").unwrap_or(&yt_channel_id);
     
-    if let Some((chan_id, n_str)) = chan_id.split_once("'") {
+    let (chan_str, n) = if let Some((chan_str, n_str)) = yt_channel_id.split_once("'") {
         let n: i64 = if n_str.is_empty() {
             1
         } else {
-            n_str.parse().context(HumanError("invalid channel n value".into()))?
+            n_str.parse().context(UserError::InvalidChannelN)?
         };
         if !(0 <= n && n < 100) {
             let err = anyhow::anyhow!("invalid channel n value {}", n)
-                .context(HumanError("invalid channel n value".into()));
+                .context(UserError::InvalidChannelN);
             return Err(err)
         }
-        Ok((chan_id.into(), n))
+        (chan_str, n)
     } else {
-        Ok((chan_id, 0))
+        (yt_channel_id, 0)
+    };
+    let chan_str = url_parse::with_scheme(chan_str.trim());
+    let chan_str = &*chan_str;
+    
+    let chan_id = if url_parse::is_url(chan_str) || chan_str.starts_with('@') {
+        let channel = match url_parse::extract_channel_ref(chan_str) {
+            Some(channel) => channel,
+            None => {
+                let err = anyhow::anyhow!("could not extract channel id from url {}", chan_str)
+                    .context(UserError::NotAChannel);
+                return Err(err)
+            }
+        };
+        youtube_req::resolve_channel(pool, &channel).await
+            .map_err(|e| {
+                e.context(UserError::ChannelLookupFailed)
+            })?
+    } else {
+        chan_str.to_string()
+    };
+    
+    if !url_parse::is_channel_id(&chan_id) {
+        let err = anyhow::anyhow!("not a channel id {}", chan_id)
+            .context(UserError::NotAChannel);
+        return Err(err)
     }
+    
+    Ok((chan_id, n))
+}
+
+/// Suggest the caller's own channels and the ones mapped in this server for slash commands
+async fn autocomplete_channel(
+    ctx: Context<'_>,
+    partial: String,
+) -> Vec<poise::AutocompleteChoice<String>> {
+    let ref pool = ctx.data().pool;
+    let user_id = ctx.author().id.0;
+    
+    let guild_channels: Vec<String> = match ctx.guild_id() {
+        Some(guild_id) => match roles_sync::get_channel_roles(pool, guild_id.0).await {
+            Ok(channels) => channels.unwrap_or_default().into_keys().collect(),
+            Err(err) => {
+                warn!(error = ?err, "autocomplete channel roles");
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+    let choices = match verification::get_channel_choices(pool, user_id, &guild_channels).await {
+        Ok(choices) => choices,
+        Err(err) => {
+            warn!(error = ?err, "autocomplete channel choices");
+            return Vec::new();
+        }
+    };
+    
+    let locale = ctx_locale(ctx).await;
+    let partial = partial.to_lowercase();
+    choices.iter()
+        .map(|choice| (choice.label(locale), choice.value()))
+        .filter(|(name, value)| name.to_lowercase().contains(&partial) || value.to_lowercase().contains(&partial))
+        // discord shows at most 25 choices
+        .take(25)
+        .map(|(name, value)| poise::AutocompleteChoice { name, value })
+        .collect()
 }
 
 /// Set up a new verification.
 #[poise::command(prefix_command, slash_command)]
 pub async fn new_token(
     ctx: Context<'_>,
-    #[description = "Youtube Channel Id"] yt_channel_id: Option<String>,
+    #[description = "Youtube Channel Id"] #[autocomplete = "autocomplete_channel"] yt_channel_id: Option<String>,
 ) -> Result<(), Error> {
     let yt_channel_id = yt_channel_id.unwrap_or_else(|| ctx.data().config.token_channel.clone());
     
+    let token = new_token_inner(ctx, &yt_channel_id).await?;
+    ctx.say(format!("{}", token)).await?;
+    
+    Ok(())
+}
+
+/// Create or reset the author's row for `yt_channel_id` and return its new token
+async fn new_token_inner(ctx: Context<'_>, yt_channel_id: &str) -> Result<String, Error> {
     let mut transaction = ctx.data().pool.begin().await?;
     
     // if url_parse::is_url(&yt_channel_id) {
@@ -263,12 +408,12 @@ pub async fn new_token(
     //         Some(id) => id,
     //         None => {
     //             let err = anyhow::anyhow!("could not extract channel id from url {}", yt_channel_id)
-    //                 .context(HumanError("could not extract channel id from url".into()));
+    //                 .context(UserError::NotAChannel);
     //             return Err(err)
     //         }
     //     };
     // }
-    let (yt_channel_id, yt_channel_n) = parse_channel_str(&yt_channel_id)?;
+    let (yt_channel_id, yt_channel_n) = parse_channel_str(&ctx.data().pool, yt_channel_id).await?;
     
     let user_id: u64 = ctx.author().id.0;
     let token = util::gen_token();
@@ -279,12 +424,18 @@ pub async fn new_token(
         VALUES  ($1,           $2,              $3,             $4     )
         ON CONFLICT ("discord_id", "yt_channel_id", "yt_channel_n")
             DO UPDATE SET
-                token = $3,
+                token = $4,
+                token_created = current_timestamp,
                 last_verified = NULL,
                 last_channel_verified = NULL,
                 last_checked = NULL,
-                failed_checks = NULL,
+                failed_checks = 0,
+                transient_failures = 0,
+                retry_after = NULL,
+                member_tier = NULL,
+                member_badge = NULL,
                 yt_video_id = NULL,
+                yt_post_id = NULL,
                 yt_comment_id = NULL,
                 user_yt_channel_id = NULL,
                 extra = '{}'
@@ -306,18 +457,16 @@ pub async fn new_token(
     
     transaction.commit().await?;
     
-    ctx.say(format!("{}", token)).await?;
-    
-    Ok(())
+    Ok(token)
 }
 
 /// Remove configuration for a channel
 #[poise::command(prefix_command, slash_command)]
 pub async fn clear_token(
     ctx: Context<'_>,
-    #[description = "Youtube Channel"] yt_channel_id: String,
+    #[description = "Youtube Channel"] #[autocomplete = "autocomplete_channel"] yt_channel_id: String,
 ) -> Result<(), Error> {
-    let (yt_channel_id, yt_channel_n) = parse_channel_str(&yt_channel_id)?;
+    let (yt_channel_id, yt_channel_n) = parse_channel_str(&ctx.data().pool, &yt_channel_id).await?;
     let ref pool = ctx.data().pool;
     let user_id: u64 = ctx.author().id.0;
     
@@ -336,9 +485,10 @@ pub async fn clear_token(
         // .bind(&yt_channel_id)
         .execute(pool).await?;
     
+    let locale = ctx_locale(ctx).await;
     poise::say_reply(
         ctx,
-        "thank you thank you",
+        locale.text("reply.thank_you"),
     ).await?;
     Ok(())
 }
@@ -351,12 +501,13 @@ pub async fn force_token(
     token: String,
 ) -> Result<(), Error> {
     let ref pool = ctx.data().pool;
-    let (yt_channel_id, yt_channel_n) = parse_channel_str(&yt_channel_id)?;
+    let (yt_channel_id, yt_channel_n) = parse_channel_str(&ctx.data().pool, &yt_channel_id).await?;
     
     sqlx::query(r#"
         UPDATE genteib.users
             SET
-                token = $4
+                token = $4,
+                token_created = current_timestamp
             WHERE
                 discord_id = $1 AND
                 yt_channel_id = $2 AND
@@ -368,9 +519,10 @@ pub async fn force_token(
         .bind(token)
         .execute(pool).await?;
     
+    let locale = ctx_locale(ctx).await;
     poise::say_reply(
         ctx,
-        "thank you thank you",
+        locale.text("reply.thank_you"),
     ).await?;
     
     Ok(())
@@ -380,9 +532,10 @@ pub async fn set_comment_inner(
     ctx: Context<'_>,
     yt_channel_id: &str,
     yt_channel_n: i64,
-    yt_video_id: &str,
+    target: &CommentTarget,
     yt_comment_id: &str,
-) -> Result<(), Error> {
+    prefetched: Option<(check_wrapper::VideoInfo, check_wrapper::MembershipStatus)>,
+) -> Result<verification::VerifyResult, Error> {
     // let (ref yt_channel_id, yt_channel_n) = parse_channel_str(yt_channel_id)?;
     
     let mut transaction = ctx.data().pool.begin().await?;
@@ -406,89 +559,166 @@ pub async fn set_comment_inner(
         UPDATE genteib.users
             SET
                 yt_video_id = $3,
-                yt_comment_id = $4
+                yt_comment_id = $4,
+                yt_post_id = $5,
+                transient_failures = 0,
+                retry_after = NULL
             WHERE
                 discord_id = $1 AND
                 yt_channel_id = $2
     "#)
         .bind(to_i(user_id))
         .bind(yt_channel_id)
-        .bind(yt_video_id)
+        .bind(target.video_id())
         .bind(yt_comment_id)
+        .bind(target.post_id())
         .execute(&mut transaction).await?;
     
     transaction.commit().await?;
     
-    let res = verification::update_verification(&ctx.data().pool, user_id, yt_channel_id, yt_channel_n).await?;
+    let source = check_wrapper::Prefetched::new(&*ctx.data().comment_source, target.clone(), yt_comment_id.to_string(), prefetched);
+    let res = verification::update_verification(&ctx.data().pool, &source, user_id, yt_channel_id, yt_channel_n).await?;
     
     match res.update_roles(&ctx.data().pool, &ctx.discord().http).await {
         Ok(None) => (),
         Ok(Some(res)) => {
             for err in res.role_errors {
-                println!("err updating role {:?}", err);
+                warn!(error = ?err, "err updating role");
             }
         },
         Err(err) => {
-            println!("err updating roles {:?}", err);
+            warn!(error = ?err, "err updating roles");
         }
     }
     
+    Ok(res)
+}
+
+/// Reply with the outcome of `set_comment_inner`
+async fn reply_verification(ctx: Context<'_>, res: verification::VerifyResult) -> Result<(), Error> {
+    let locale = ctx_locale(ctx).await;
     if !res.is_member {
-        let mut msg = "thank you thank you (not a member)".to_string();
+        let mut msg = locale.text("reply.thank_you_not_member");
         use std::fmt::Write;
         for err in res.errors {
-            write!(msg, "\n`  `{}", err).unwrap();
+            write!(msg, "\n`  `{}", err.render(locale)).unwrap();
         }
         ctx.say(msg).await?;
     } else {
         // ctx.reply("thank you thank you").await?;
         poise::say_reply(
             ctx,
-            "thank you thank you",
+            locale.text("reply.thank_you"),
         ).await?;
     }
     Ok(())
 }
 
+/// Link your YouTube membership step by step in DMs
+#[poise::command(prefix_command, slash_command)]
+pub async fn verify(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+    wizard::run(ctx).await
+}
+
 /// Sets comment for verification
 #[poise::command(prefix_command, slash_command)]
 pub async fn set_comment(
     ctx: Context<'_>,
-    #[description = "Video Url"] yt_video_url: String,
+    #[description = "Video or Post Url"] yt_video_url: String,
 ) -> Result<(), Error> {
+    let link = parse_comment_link(ctx, &yt_video_url).await?;
+    let res = set_comment_inner(ctx, &link.channel_id, 0, &link.target, &link.comment_id, link.prefetched).await?;
+    reply_verification(ctx, res).await
+}
+
+/// A comment link resolved by `parse_comment_link`
+pub struct CommentLink {
+    pub channel_id: String,
+    pub target: CommentTarget,
+    pub comment_id: String,
+    /// the comment as loaded to find the channel of a post, reused by the check
+    pub prefetched: Option<(check_wrapper::VideoInfo, check_wrapper::MembershipStatus)>,
+}
+
+/// Channel, video or post, and comment id of a comment link
+async fn parse_comment_link(ctx: Context<'_>, yt_video_url: &str) -> Result<CommentLink, Error> {
     let yt_video_url = yt_video_url
         .strip_prefix("<").unwrap_or(&yt_video_url)
         .strip_suffix(">").unwrap_or(&yt_video_url);
+    let yt_video_url = url_parse::with_scheme(yt_video_url.trim());
     
-    let (video_id, comment_id) = match url_parse::extract_video_comment_id(&yt_video_url) {
-        Some((video_id, Some(comment_id))) => (video_id, comment_id),
-        _ => {
+    if let Some(link) = url_parse::extract_post_link(&yt_video_url) {
+        let comment_id = link.comment_id.ok_or_else(|| {
+            anyhow::anyhow!("no comment id in post url {}", yt_video_url)
+                .context(UserError::PostLinkWithoutComment)
+        })?;
+        let target = CommentTarget::Post(link.post_id);
+        let (video_info, status) = get_post_channel(ctx, &target, &comment_id).await?;
+        return Ok(CommentLink {
+            channel_id: video_info.channel_id.clone(),
+            target,
+            comment_id,
+            prefetched: Some((video_info, status)),
+        });
+    }
+    
+    let (video_id, comment_id) = match url_parse::extract_video_link(&yt_video_url) {
+        Some(url_parse::VideoLink { video_id, comment_id: Some(comment_id), kind }) => {
+            tracing::debug!(?kind, %video_id, "set comment link");
+            (video_id, comment_id)
+        }
+        Some(link) => {
+            let err = anyhow::anyhow!("no comment id in {:?} url {}", link.kind, yt_video_url)
+                .context(UserError::VideoLinkWithoutComment);
+            return Err(err);
+        }
+        None => {
             let err = anyhow::anyhow!("could not extract video or comment id from url {}", yt_video_url)
-                .context(HumanError("Could not extract video and comment id from url".into()));
+                .context(UserError::NotACommentLink);
             return Err(err);
         }
     };
     
-    let req_url = format!("https://www.youtube.com/watch?v={}", video_id);
-    let channel_id = youtube_req::get_channel_id(&req_url).await
+    let channel_id = youtube_req::get_channel_id(&ctx.data().pool, &video_id).await
         .map_err(|e| {
-            e.context(HumanError("Could not fetch channel id for video".into()))
+            e.context(UserError::VideoLookupFailed)
         })?;
     
-    set_comment_inner(ctx, &channel_id, 0, &video_id, &comment_id).await
+    Ok(CommentLink {
+        channel_id,
+        target: CommentTarget::Video(video_id),
+        comment_id,
+        prefetched: None,
+    })
+}
+
+/// Posts have no cached lookup, the owner comes from loading the comment itself
+///
+/// The whole result is returned so the check can reuse it instead of loading the comment again.
+async fn get_post_channel(ctx: Context<'_>, target: &CommentTarget, comment_id: &str) -> Result<(check_wrapper::VideoInfo, check_wrapper::MembershipStatus), Error> {
+    let res = ctx.data().comment_source.fetch(target, comment_id).await
+        .map_err(|e| {
+            anyhow::Error::from(e).context(UserError::PostLookupFailed)
+        })?;
+    Ok(res)
 }
 
 /// Sets comment for verification
 #[poise::command(prefix_command, slash_command)]
 pub async fn set_comment_b(
     ctx: Context<'_>,
-    #[description = "Youtube Channel Id"] yt_channel_id: String,
-    #[description = "Youtube Video Id"]   yt_video_id: String,
+    #[description = "Youtube Channel Id"] #[autocomplete = "autocomplete_channel"] yt_channel_id: String,
+    #[description = "Youtube Video or Post Id"] yt_video_id: String,
     #[description = "Youtube Comment Id"] yt_comment_id: String,
+    #[description = "The id is of a community post"] post: Option<bool>,
 ) -> Result<(), Error> {
-    let (yt_channel_id, yt_channel_n) = parse_channel_str(&yt_channel_id)?;
+    let (yt_channel_id, yt_channel_n) = parse_channel_str(&ctx.data().pool, &yt_channel_id).await?;
     
-    set_comment_inner(ctx, &yt_channel_id, yt_channel_n, &yt_video_id, &yt_comment_id).await
+    let target = CommentTarget::new(yt_video_id, post.unwrap_or(false));
+    let res = set_comment_inner(ctx, &yt_channel_id, yt_channel_n, &target, &yt_comment_id, None).await?;
+    reply_verification(ctx, res).await
 }
 
 async fn status_inner(
@@ -497,15 +727,15 @@ async fn status_inner(
 ) -> Result<(), Error> {
     let ref pool = ctx.data().pool;
     
-    let statuses = verification::get_statuses(pool, user_id).await
-        .map_err(|e| { dbg!(&e); e })?;
+    let statuses = verification::get_statuses(pool, user_id).await?;
+    let locale = ctx_locale(ctx).await;
     
     if statuses.is_empty() {
-        poise::say_reply(ctx, "No configured channels").await?;
+        poise::say_reply(ctx, locale.text("reply.no_channels")).await?;
     }
     
     for status in statuses {
-        poise::say_reply(ctx, &status.format_message()).await?;
+        poise::say_reply(ctx, &status.format_message(locale)).await?;
     }
     
     Ok(())
@@ -521,6 +751,70 @@ pub async fn status(
     status_inner(ctx, user_id).await
 }
 
+/// DM yourself a JSON file with everything the bot stores about you
+#[poise::command(prefix_command, slash_command)]
+pub async fn my_data(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id.0;
+    
+    let data = verification::export_user_data(&ctx.data().pool, user_id).await?;
+    let json = serde_json::to_vec_pretty(&data)?;
+    
+    let locale = ctx_locale(ctx).await;
+    ctx.author().direct_message(ctx.discord(), |m| {
+        m
+            .content(locale.text("reply.my_data"))
+            .add_file((json.as_slice(), "gentei_data.json"))
+    }).await?;
+    poise::say_reply(ctx, locale.text("reply.my_data_sent")).await?;
+    
+    Ok(())
+}
+
+/// Parse a language code like "ja", or fail with the available languages
+fn parse_locale(code: &str) -> Result<Locale, Error> {
+    Locale::from_code(code).ok_or_else(|| {
+        anyhow::anyhow!("unknown locale {}", code)
+            .context(UserError::UnknownLanguage(code.to_string()))
+    })
+}
+
+/// Show or set the language the bot uses with you
+///
+/// Run with "auto" to use the server or Discord language again.
+#[poise::command(prefix_command, slash_command)]
+pub async fn language(
+    ctx: Context<'_>,
+    #[description = "Language code, like en, ja or id"] code: Option<String>,
+) -> Result<(), Error> {
+    let ref pool = ctx.data().pool;
+    let user_id = ctx.author().id.0;
+    
+    let msg = match code.as_deref() {
+        None => {
+            let locale = ctx_locale(ctx).await;
+            let available = Locale::ALL.iter()
+                .map(|l| format!("{} ({})", l.code(), l))
+                .collect::<Vec<_>>()
+                .join(", ");
+            locale.fmt("reply.language_current", &[("language", &locale), ("available", &available)])
+        }
+        Some("auto") => {
+            locale::set_user_locale(pool, user_id, None).await?;
+            ctx_locale(ctx).await.text("reply.language_cleared")
+        }
+        Some(code) => {
+            let locale = parse_locale(code)?;
+            locale::set_user_locale(pool, user_id, Some(locale)).await?;
+            locale.fmt("reply.language_set", &[("language", &locale)])
+        }
+    };
+    poise::say_reply(ctx, msg).await?;
+    
+    Ok(())
+}
+
 #[poise::command(prefix_command, owners_only)]
 pub async fn statusu (
     ctx: Context<'_>,
@@ -529,72 +823,416 @@ pub async fn statusu (
     status_inner(ctx, user_id).await
 }
 
+/// Show recent verification attempts for a user
+#[poise::command(prefix_command, owners_only)]
+pub async fn events(
+    ctx: Context<'_>,
+    user_id: u64,
+    n: Option<i64>,
+) -> Result<(), Error> {
+    let ref pool = ctx.data().pool;
+    
+    let events = verification::get_events(pool, user_id, n.unwrap_or(10).clamp(1, 50)).await?;
+    let locale = ctx_locale(ctx).await;
+    
+    if events.is_empty() {
+        poise::say_reply(ctx, locale.text("admin.no_events")).await?;
+        return Ok(());
+    }
+    
+    let text = events.iter()
+        .map(|event| event.format_message())
+        .collect::<Vec<_>>()
+        .join("\n");
+    
+    // one message when it fits, a file otherwise
+    if text.chars().count() <= 2000 {
+        poise::say_reply(ctx, text).await?;
+    } else {
+        let msg = locale.fmt("admin.events_attached", &[("count", &events.len())]);
+        ctx.send(|m| {
+            m
+                .content(msg.clone())
+                .attachment((text.as_bytes(), "events.txt").into())
+        }).await?;
+    }
+    
+    Ok(())
+}
+
+/// Map a role to a channel, optionally only for members with at least `min_tier` months
+///
+/// Run without a channel to remove the role mapping.
 #[poise::command(prefix_command, owners_only)]
 pub async fn set_role(
     ctx: Context<'_>,
     server_id: u64,
     role_id: u64,
     channel_id: Option<String>,
+    min_tier: Option<i64>,
 ) -> Result<(), Error> {
     let ref pool = ctx.data().pool;
     
-    let role_id_str = role_id.to_string();
-    
-    use std::collections::BTreeMap;
-    use sqlx::types::Json;
     if let Some(channel_id) = channel_id {
-        let mut role_map = BTreeMap::new();
-        role_map.insert(&role_id_str, &channel_id);
-        
-        sqlx::query(r#"
-            INSERT INTO genteib.servers (server_id, roles)
-            VALUES ($1, $4)
-            --SET
-            --    roles = 
-            --WHERE
-            --    
-            ON CONFLICT ("server_id")
-                DO UPDATE SET
-                    roles = servers.roles || EXCLUDED.roles
-        "#)
-            .bind(to_i(server_id))
-            .bind(to_i(role_id))
-            .bind(&channel_id)
-            .bind(Json(role_map))
-            .execute(pool).await?;
+        roles_sync::add_role_mapping(pool, server_id, role_id, &channel_id, min_tier.unwrap_or(0)).await?;
     } else {
-        sqlx::query(r#"
-            UPDATE genteib.servers
-            SET
-                roles = roles - $2
-            WHERE
-                server_id = $1
-        "#)
-            .bind(to_i(server_id))
-            .bind(role_id_str)
-            .execute(pool).await?;
+        roles_sync::remove_role_mapping(pool, server_id, role_id).await?;
     }
     
+    let locale = ctx_locale(ctx).await;
     poise::say_reply(
         ctx,
-        "thank you thank you",
+        locale.text("reply.thank_you"),
     ).await?;
     
     Ok(())
 }
 
+/// Check that the bot is allowed to hand out `role` in `guild_id`
+async fn check_manageable_role(
+    ctx: Context<'_>,
+    guild_id: serenity::GuildId,
+    role: &serenity::Role,
+) -> Result<(), Error> {
+    if role.guild_id != guild_id {
+        return Err(anyhow::anyhow!("role {} is not in guild {}", role.id, guild_id)
+            .context(UserError::RoleNotInServer));
+    }
+    if role.id.0 == guild_id.0 || role.managed {
+        return Err(anyhow::anyhow!("role {} can not be assigned", role.id)
+            .context(UserError::RoleNotAssignable));
+    }
+    
+    let ref http = ctx.discord().http;
+    let bot_user = http.get_current_user().await?;
+    let bot_member = guild_id.member(ctx.discord(), bot_user.id).await?;
+    let guild_roles = guild_id.roles(ctx.discord()).await?;
+    
+    // the @everyone role shares the guild id and applies to everyone
+    let mut permissions = guild_roles.get(&serenity::RoleId(guild_id.0))
+        .map(|r| r.permissions)
+        .unwrap_or_else(serenity::Permissions::empty);
+    let mut top_position = 0;
+    for role_id in bot_member.roles.iter() {
+        if let Some(bot_role) = guild_roles.get(role_id) {
+            permissions |= bot_role.permissions;
+            top_position = top_position.max(bot_role.position);
+        }
+    }
+    
+    if !(permissions.manage_roles() || permissions.administrator()) {
+        return Err(anyhow::anyhow!("missing manage roles in guild {}", guild_id)
+            .context(UserError::MissingManageRoles));
+    }
+    if top_position <= role.position {
+        return Err(anyhow::anyhow!("role {} above bot in guild {}", role.id, guild_id)
+            .context(UserError::RoleAboveBot));
+    }
+    
+    Ok(())
+}
+
+fn require_guild(ctx: Context<'_>) -> Result<serenity::GuildId, Error> {
+    ctx.guild_id().ok_or_else(|| {
+        anyhow::anyhow!("guild command run outside guild")
+            .context(UserError::GuildOnly)
+    })
+}
+
+/// Give a role to verified members of a youtube channel
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn add_role(
+    ctx: Context<'_>,
+    #[description = "Role to give"] role: serenity::Role,
+    #[description = "Youtube Channel url or id"] yt_channel: String,
+    #[description = "Minimum membership months for this role"] min_tier: Option<i64>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    
+    let (yt_channel_id, _) = parse_channel_str(&ctx.data().pool, &yt_channel).await?;
+    if !(yt_channel_id.starts_with("UC") && yt_channel_id.len() == 24) {
+        let err = anyhow::anyhow!("invalid channel id {}", yt_channel_id)
+            .context(UserError::NotAChannel);
+        return Err(err);
+    }
+    let min_tier = min_tier.unwrap_or(0);
+    if min_tier < 0 {
+        let err = anyhow::anyhow!("invalid min tier {}", min_tier)
+            .context(UserError::NegativeTier);
+        return Err(err);
+    }
+    
+    check_manageable_role(ctx, guild_id, &role).await?;
+    
+    roles_sync::add_role_mapping(&ctx.data().pool, guild_id.0, role.id.0, &yt_channel_id, min_tier).await?;
+    
+    let locale = ctx_locale(ctx).await;
+    poise::say_reply(
+        ctx,
+        locale.fmt("admin.role_added", &[("role", &role.name), ("channel_id", &yt_channel_id)]),
+    ).await?;
+    
+    Ok(())
+}
+
+/// Stop giving a role to verified members
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn remove_role(
+    ctx: Context<'_>,
+    #[description = "Role to stop giving, a mention or the id of a deleted role"] role: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    
+    // deleted roles can only be given by id
+    let role_id = poise::serenity::utils::parse_role(role.trim())
+        .or_else(|| role.trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid role {:?}", role).context(UserError::NotARole(role.clone())))?;
+    
+    let removed = roles_sync::remove_role_mapping(&ctx.data().pool, guild_id.0, role_id).await?;
+    
+    let role_name = ctx.discord().cache.role(guild_id, role_id).await
+        .map_or_else(|| role_id.to_string(), |role| role.name);
+    let locale = ctx_locale(ctx).await;
+    let msg = if removed {
+        locale.fmt("admin.role_removed", &[("role", &role_name)])
+    } else {
+        locale.fmt("admin.role_not_mapped", &[("role", &role_name)])
+    };
+    poise::say_reply(ctx, msg).await?;
+    
+    Ok(())
+}
+
+/// List the roles given to verified members in this server
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn list_roles(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    
+    let channels = roles_sync::get_channel_roles(&ctx.data().pool, guild_id.0).await?
+        .unwrap_or_default();
+    let locale = ctx_locale(ctx).await;
+    
+    if channels.is_empty() {
+        poise::say_reply(ctx, locale.text("admin.no_roles")).await?;
+        return Ok(());
+    }
+    
+    let guild_roles = guild_id.roles(ctx.discord()).await?;
+    let deleted_role = locale.text("admin.deleted_role");
+    
+    use std::fmt::Write;
+    let mut msg = String::new();
+    for (yt_channel_id, roles) in channels.iter() {
+        write!(msg, "<https://www.youtube.com/channel/{}>\n", yt_channel_id).unwrap();
+        for (role_id, min_tier) in roles.iter() {
+            let name = guild_roles.get(role_id)
+                .map(|r| r.name.as_str())
+                .unwrap_or(&deleted_role);
+            write!(msg, "`  `{} ({})", name, role_id.0).unwrap();
+            if *min_tier > 0 {
+                msg.push_str(&locale.fmt("admin.role_min_tier", &[("months", min_tier)]));
+            }
+            msg.push('\n');
+        }
+    }
+    poise::say_reply(ctx, msg).await?;
+    
+    Ok(())
+}
+
+/// List members of this server that hold a mapped role, with their verification state
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn member_report(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    
+    let ref http = ctx.discord().http;
+    let rows = roles_sync::member_report(&ctx.data().pool, http, guild_id.0).await
+        .map_err(|e| e.context(UserError::ServerNotConfigured))?;
+    
+    let mut csv = String::new();
+    csv.push_str(roles_sync::MemberReportRow::CSV_HEADER);
+    csv.push('\n');
+    for row in rows.iter() {
+        csv.push_str(&row.to_csv());
+        csv.push('\n');
+    }
+    
+    let locale = ctx_locale(ctx).await;
+    let mut msg = String::new();
+    for row in rows.iter() {
+        let channel = row.channel_name.as_deref().unwrap_or(&row.yt_channel_id);
+        let mut line = match (row.yt_channel_n, row.last_verified) {
+            (None, _) => locale.fmt("admin.report_not_configured", &[
+                ("member", &row.discord_name),
+                ("channel_id", &row.yt_channel_id),
+            ]),
+            (Some(_), None) => locale.fmt("admin.report_not_verified", &[
+                ("member", &row.discord_name),
+                ("channel", &channel),
+                ("failed_checks", &row.failed_checks.unwrap_or(0)),
+            ]),
+            (Some(_), Some(verified)) => locale.fmt("admin.report_verified", &[
+                ("member", &row.discord_name),
+                ("channel", &channel),
+                ("verified", &format!("<t:{}>", verified.timestamp())),
+                ("checked", &row.last_checked.map(|d| format!("<t:{}>", d.timestamp())).unwrap_or_else(|| "-".into())),
+            ]),
+        };
+        line.push('\n');
+        // leave room for the truncation note
+        if msg.len() + line.len() > 1900 {
+            msg.push_str(&locale.text("admin.report_truncated"));
+            break;
+        }
+        msg.push_str(&line);
+    }
+    if rows.is_empty() {
+        msg.push_str(&locale.text("admin.report_empty"));
+    }
+    
+    ctx.send(|m| {
+        m
+            .content(msg.clone())
+            .attachment((csv.as_bytes(), "members.csv").into())
+    }).await?;
+    
+    Ok(())
+}
+
+/// Show or change how verifications expire and are re-checked in this server
+///
+/// Channels mapped in several servers use the strictest settings of those servers.
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn server_settings(
+    ctx: Context<'_>,
+    #[description = "Hours a verification gives roles for"] verify_expiry_hours: Option<i64>,
+    #[description = "Hours between re-checks of members"] recheck_hours: Option<i64>,
+    #[description = "Failed checks before a member is no longer re-checked"] max_failed_checks: Option<i64>,
+    #[description = "Language for members without their own, like en, ja or id"] language: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    
+    if verify_expiry_hours.map_or(false, |h| h <= 0) || recheck_hours.map_or(false, |h| h <= 0) {
+        let err = anyhow::anyhow!("invalid interval {:?} {:?}", verify_expiry_hours, recheck_hours)
+            .context(UserError::IntervalTooShort);
+        return Err(err);
+    }
+    if max_failed_checks.map_or(false, |n| n < 0) {
+        let err = anyhow::anyhow!("invalid max failed checks {:?}", max_failed_checks)
+            .context(UserError::NegativeMaxFailedChecks);
+        return Err(err);
+    }
+    
+    let locale = language.as_deref().map(parse_locale).transpose()?;
+    
+    let settings = roles_sync::update_server_settings(
+        &ctx.data().pool,
+        guild_id.0,
+        verify_expiry_hours,
+        recheck_hours,
+        max_failed_checks,
+        locale.map(|l| l.code()),
+    ).await?;
+    
+    let reply_locale = ctx_locale(ctx).await;
+    let language = match settings.locale.as_deref().and_then(Locale::from_code) {
+        Some(locale) => locale.to_string(),
+        None => reply_locale.text("admin.settings_discord_language"),
+    };
+    let mut msg = reply_locale.fmt("admin.settings", &[
+        ("verify_expiry_hours", &settings.verify_expiry_hours),
+        ("recheck_hours", &settings.recheck_hours),
+        ("max_failed_checks", &settings.max_failed_checks),
+        ("language", &language),
+    ]);
+    if settings.recheck_hours >= settings.verify_expiry_hours {
+        msg.push('\n');
+        msg.push_str(&reply_locale.text("admin.settings_recheck_warning"));
+    }
+    poise::say_reply(ctx, msg).await?;
+    
+    Ok(())
+}
+
+/// Replace this server's guide for a language, messages are separated by lines of >---
+///
+/// Placeholders: {server}, {channels}, {roles}, {video_id}, {channel_id} and {support_text}.
+/// Members get the guide in their language, or in another language the server has one in.
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn set_guide(
+    ctx: Context<'_>,
+    #[description = "Language of this guide, like en, ja or id"] language: String,
+    #[description = "Guide text, messages separated by >---"] #[rest] text: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let locale = parse_locale(&language)?;
+    
+    let sections = guide::split_sections(&text);
+    if sections.is_empty() {
+        return Err(anyhow::anyhow!("empty guide").context(UserError::GuideEmpty));
+    }
+    if sections.len() > guide::MAX_SECTIONS {
+        let err = anyhow::anyhow!("guide has {} sections", sections.len())
+            .context(UserError::TooManyGuideSections);
+        return Err(err);
+    }
+    if let Some(n) = sections.iter().position(|x| x.chars().count() > guide::MAX_SECTION_LEN) {
+        let err = anyhow::anyhow!("guide section {} too long", n)
+            .context(UserError::GuideSectionTooLong{ section: n + 1 });
+        return Err(err);
+    }
+    
+    guide::set_server_guide(&ctx.data().pool, guild_id.0, locale, &sections).await?;
+    
+    let reply_locale = ctx_locale(ctx).await;
+    poise::say_reply(
+        ctx,
+        reply_locale.fmt("admin.guide_saved", &[("language", &locale), ("n", &sections.len())]),
+    ).await?;
+    
+    Ok(())
+}
+
+/// Go back to the default guide for a language, or for all languages
+#[poise::command(prefix_command, slash_command, required_permissions = "MANAGE_ROLES")]
+pub async fn clear_guide(
+    ctx: Context<'_>,
+    #[description = "Language of the guide to remove, all if not given"] language: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let locale = language.as_deref().map(parse_locale).transpose()?;
+    
+    let removed = guide::clear_server_guide(&ctx.data().pool, guild_id.0, locale).await?;
+    
+    let reply_locale = ctx_locale(ctx).await;
+    let msg = if removed > 0 {
+        reply_locale.text("admin.guide_cleared")
+    } else {
+        reply_locale.text("admin.guide_not_found")
+    };
+    poise::say_reply(ctx, msg).await?;
+    
+    Ok(())
+}
+
 #[poise::command(prefix_command, owners_only)]
 pub async fn test_check(
     ctx: Context<'_>,
     yt_video_id: String,
     yt_comment_id: String,
+    post: Option<bool>,
 ) -> Result<(), Error> {
-    use check_wrapper::{check_member, Member, Not, NotFound};
-    let res = check_member(&yt_video_id, &yt_comment_id).await?;
+    use check_wrapper::{Member, Not, NotFound};
+    let target = CommentTarget::new(yt_video_id, post.unwrap_or(false));
+    let res = ctx.data().comment_source.fetch(&target, &yt_comment_id).await?;
     
     match res {
-        (_, Member{ channel_id, .. }) => {
-            ctx.say(format!("member {}", channel_id)).await?;
+        (_, Member{ channel_id, tier, badge, .. }) => {
+            ctx.say(format!("member {} tier {} ({})", channel_id, tier, badge)).await?;
         }
         (_, Not{ .. }) => {
             ctx.say("not member").await?;
@@ -615,15 +1253,15 @@ pub async fn test_verify(
 ) -> Result<(), Error> {
     use verification::update_verification;
     // let mut transaction = ctx.data().pool.begin().await?;
-    let (yt_channel_id, yt_channel_n) = parse_channel_str(&yt_channel_id)?;
+    let (yt_channel_id, yt_channel_n) = parse_channel_str(&ctx.data().pool, &yt_channel_id).await?;
     
-    update_verification(&ctx.data().pool, discord_id, &yt_channel_id, yt_channel_n).await
-        .map_err(|e| { println!("{:?}", e); e })?;
+    update_verification(&ctx.data().pool, &*ctx.data().comment_source, discord_id, &yt_channel_id, yt_channel_n).await?;
     
     // transaction.commit().await?;
+    let locale = ctx_locale(ctx).await;
     poise::say_reply(
         ctx,
-        "thank you thank you",
+        locale.text("reply.thank_you"),
     ).await?;
     
     Ok(())
@@ -638,9 +1276,10 @@ pub async fn sync_members(
     let guild_id = match guild_id {
         Some(guild_id) => guild_id,
         None => {
+            let locale = ctx_locale(ctx).await;
             poise::say_reply(
                 ctx,
-                "must specify a guild id or run in a guild",
+                locale.text("admin.sync_needs_guild"),
             ).await?;
             return Err(::anyhow::anyhow!("sync_members run without guild id").into());
         }
@@ -650,8 +1289,7 @@ pub async fn sync_members(
     let ref pool = ctx.data().pool;
     
     // roles_sync::sync_roles(&ctx, guild_id).await
-    roles_sync::sync_roles(&pool, &http, guild_id).await
-        .map_err(|e| { dbg!(&e); e })?;
+    roles_sync::sync_roles(&pool, &http, guild_id).await?;
     
     Ok(())
 }
@@ -674,8 +1312,12 @@ pub async fn help(
 async fn check_prefix<'a>(
     _ctx: &'a poise::serenity_prelude::Context,
     msg: &'a poise::serenity_prelude::Message,
-    _data: &'a Data,
+    data: &'a Data,
 ) -> Option<String> {
+    // links sent to a wizard are not commands, only the normal prefix works meanwhile
+    if data.active_wizards.contains(msg.author.id) {
+        return None;
+    }
     if msg.guild_id.is_none() && !msg.content.starts_with(">>'") {
         Some("".into())
     } else {
@@ -683,220 +1325,158 @@ async fn check_prefix<'a>(
     }
 }
 
+/// Every command runs through here, so the caller is logged once instead of on each command
+async fn pre_command(ctx: Context<'_>) {
+    let span = tracing::info_span!("command", user = %ctx.author().id, guild = ?ctx.guild_id());
+    span.in_scope(|| info!("command invoked"));
+}
+
 async fn error_handler(error: Error, ctx: poise::ErrorContext<'_, Data, Error>) {
     let err_uuid = util::gen_uuid();
     if &err_uuid[17..] != "000000000000000" {
-        println!("unexpected uuid tail {} on {}", &err_uuid[17..], err_uuid);
+        warn!(uuid = %err_uuid, "unexpected uuid tail {}", &err_uuid[17..]);
     }
     let err_uuid = &err_uuid[..17];
     
     // panic!("a");
-    println!("ERROR ({}): {:?}", err_uuid, error);
+    let user = match &ctx {
+        poise::ErrorContext::Command(ce_context) => Some(ce_context.ctx().author().id.0),
+        _ => None,
+    };
+    error!(err_uuid, user = ?user, error = ?error, "command error");
     if let poise::ErrorContext::Command(ce_context) = ctx {
         let ctx = ce_context.ctx();
         
-        use std::fmt::Write;
-        let mut msg = String::new();
-        write!(msg, "error ({})", err_uuid).unwrap();
+        let user_error = error.downcast_ref::<UserError>().cloned()
+            .or_else(|| {
+                error.downcast_ref::<poise::ArgumentParseError>()
+                    .map(|e| UserError::InvalidArgument(e.to_string()))
+            });
         
-        // let a: Option<&anyhow::Error> = error.downcast_ref::<anyhow::Error>();
-        // println!("{:?}", a);
-        let x: Option<&verification::HumanContext> = error.downcast_ref();
-        // println!("{:?}", x);
-        if let Some(e) = x {
-            write!(msg, ": {}", e).unwrap();
-        }
-        
-        if let Some(e) = error.downcast_ref::<HumanError>() {
-            write!(msg, ": {}", e).unwrap();
-        }
-        
-        if let Some(e) = error.downcast_ref::<poise::ArgumentParseError>() {
-            write!(msg, ": {}", e).unwrap();
-        }
-        
-        // trait Test: std::fmt::Display + Send + Sync + std::fmt::Debug + 'static + Sized {}
-        
-        // let x: Option<&Test> = error.downcast_ref();
+        // anything else only shows the id to look up in the logs
+        let locale = ctx_locale(ctx).await;
+        let msg = match user_error {
+            Some(e) => format!("{} ({}): {}", e.severity().label(locale), err_uuid, e.with_hint(locale)),
+            None => format!("{} ({})", locale.text("severity.error"), err_uuid),
+        };
         
         if let Err(err) = poise::say_reply(
             ctx,
             &msg,
             // "error",
         ).await {
-            dbg!(err);
+            warn!(err_uuid, error = ?err, "could not send error reply");
         }
     }
 }
 
 #[tokio::main]
 async fn main() {
+    // exits with the validation errors if the config is invalid
+    lazy_static::initialize(&SETTINGS);
+    // panics on a broken catalog before anything is started
+    lazy_static::initialize(&locale::CATALOGS);
+    logging::init(&SETTINGS.logging);
+    
     let pool = get_pool().await.expect("failed to get pool");
     
-    let token = std::env::var("discord_auth").expect("discord_auth env var not set");
+    let comment_source = check_wrapper::source_from_settings(&SETTINGS.check).expect("failed to set up comment source");
+    
+    let token = SETTINGS.discord.auth.expose().to_string();
     
-    let owners: std::collections::HashSet<_> = std::env::var("owners")
-        .expect("owners env var not set")
-        .split(",")
-        .map(|x| x.trim().parse().expect("invalid owner value"))
-        .map(|x| UserId(x))
+    let owners: std::collections::HashSet<_> = SETTINGS.discord.owners.iter()
+        .map(|&x| UserId(x))
         .collect();
     
     let config = Config {
-        token_channel: std::env::var("token_channel").expect("token_channel env_var not set"),
-        token_video: std::env::var("token_video").expect("token_video env_var not set"),
-        goojf: GOOJF.clone(),
-        
+        token_channel: SETTINGS.youtube.token_channel.clone(),
+        token_video: SETTINGS.youtube.token_video.clone(),
+        goojf: SETTINGS.youtube.goojf.expose().to_string(),
     };
     
-    let guide_text: Vec<String> = {
-        let support_text = std::env::var("support_text").unwrap_or("".into());
-        
-        let parts = GUIDE
-            .replace("{video_id}", &config.token_video)
-            .replace("{channel_id}", &config.token_channel)
-            .replace("{support_text}", &support_text)
-            .split(">---")
-            .map(|x| x.trim().to_string())
-            .collect();
-        parts
-    };
+    let guide_text = Locale::ALL.iter()
+        .map(|&locale| (locale, guide::builtin(locale)))
+        .collect();
     
     let cmd = std::env::args().skip(1).next();
     
+    let intervals = daemon::Intervals::from_settings(&SETTINGS.service);
+    
+    // SIGTERM / ctrl-c stops the background jobs, in-flight checks get a grace period
+    let (shutdown_controller, shutdown) = shutdown::channel(Duration::from_secs(SETTINGS.service.shutdown_grace));
+    
+    metrics::spawn_server(SETTINGS.metrics.addr.clone(), shutdown.clone());
+    
+    // "service" runs the bot together with the background jobs
+    let mut service = false;
+    
     match cmd.as_ref().map(|s| s.as_str()) {
         Some("verify_daemon") => {
-            println!("running verify daemon");
+            info!("running verify daemon");
             // let client = poise::serenity::client::Client::builder(&token)
             //     .await.expect("serenity client start");
             use std::sync::Arc;
-            use poise::serenity::CacheAndHttp;
             let http = Arc::new(poise::serenity::http::client::Http::new_with_token(&token));
-            // let cache_http = poise::serenity::CacheAndHttp {
-            //     // cache: Arc::new(serenity::cache::Cache::new()),
-            //     // update_cache_timeout: None,
-            //     http: http.clone(),
-            //     ..CacheAndHttp::default(),
-            // };
-            let mut cache_http = CacheAndHttp::default();
-            cache_http.http = http.clone();
+            let cache_http = daemon::cache_http(http);
             
             // return;
             // let http = client.cache_and_http.http.clone();
-            loop {
-                match verification::verify_pending(&pool, 100).await {
-                    Ok(results) => {
-                        for res in results {
-                            match res.update_roles(&pool, &http).await {
-                                Ok(None) => (),
-                                Ok(Some(res)) => {
-                                    for err in res.role_errors {
-                                        println!("err updating role {:?}", err);
-                                    }
-                                },
-                                Err(err) => {
-                                    println!("err updating roles {:?}", err);
-                                }
-                            }
-                            
-                            async fn send_message(cache_http: &CacheAndHttp, user_id: u64, msg: &str) -> Result<(), anyhow::Error> {
-                                let user_id = poise::serenity::model::id::UserId(user_id);
-                                let user = user_id.to_user(cache_http).await?;
-                                user.direct_message(cache_http, |m| {
-                                    m.content(msg)
-                                }).await?;
-                                
-                                Ok(())
-                            }
-                            
-                            // println!("{:?}", res);
-                            let msg = if res.became_member() {
-                                Some(format!("Membership to {} ({}) is now verified", res.channel_name, res.yt_channel_id))
-                            } else if res.became_non_member() {
-                                let mut msg = format!("Membership to {} ({}) is no longer verified", res.channel_name, res.yt_channel_id);
-                                use std::fmt::Write;
-                                for err in res.errors {
-                                    write!(msg, "\n`  `{}", err).unwrap();
-                                }
-                                Some(msg)
-                            } else {
-                                None
-                            };
-                            if let Some(msg) = msg {
-                                match send_message(&cache_http, res.discord_id, &msg).await {
-                                    Ok(()) => (),
-                                    Err(err) => {
-                                        println!("could not send become member message {:?}", err);
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    Err(err) => { dbg!(err); },
-                }
-                tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-            }
+            let task = tokio::spawn(daemon::run_verify_loop(pool.clone(), comment_source, cache_http, intervals.verify, shutdown));
+            shutdown::wait_for_signal().await;
+            info!("shutdown requested");
+            shutdown_controller.trigger();
+            task.await.expect("verify loop panicked");
+            pool.close().await;
+            return
         },
         Some("sync_roles") => {
-            println!("syncing roles");
-            let rows: Vec<(i64,)> = sqlx::query_as(r#"
-                SELECT server_id
-                FROM genteib.servers
-            "#)
-                .fetch_all(&pool).await
-                .expect("get server list");
-            
+            info!("syncing roles");
             let http = poise::serenity::http::client::Http::new_with_token(&token);
             
-            for (server_id,) in rows {
-                let server_id = from_i(server_id);
-                println!("syncing roles for server {}", server_id);
-                match roles_sync::sync_roles(&pool, &http, server_id).await {
-                    Ok(()) => (),
-                    Err(err) => {
-                        println!("sync roles error {} {:?}", server_id, err);
-                    }
-                }
-            }
-            println!("sync complete");
+            daemon::sync_all_roles(&pool, &http, &shutdown).await
+                .expect("get server list");
+            info!("sync complete");
             return
         }
         Some("check_over_paired") => {
-            println!("running over paired check");
-            let res = verification::check_over_paired_discord_ids(&pool).await;
-            match res {
-                Ok(removed) => {
-                    if !removed.is_empty() {
-                        dbg!(removed);
-                    }
-                }
-                Err(err) => { dbg!(err); }
-            }
+            info!("running over paired check");
+            daemon::check_over_paired(&pool).await;
             return
         }
+        Some("service") => {
+            service = true;
+        }
         None => (),
         _ => {
             // panic!("unknown cmd {:?}", cmd);
-            println!("unknown cmd {:?}", cmd);
+            error!(?cmd, "unknown cmd");
             return
         }
     }
     
-    poise::Framework::build()
+    let task_shutdown = shutdown.clone();
+    let task_pool = pool.clone();
+    let framework = poise::Framework::build()
         // .prefix(">>'")
         .token(token)
-        .user_data_setup(move |_ctx, _ready, _framework| {
+        .user_data_setup(move |ctx, _ready, _framework| {
+            if service {
+                daemon::spawn_service_tasks(pool.clone(), comment_source.clone(), ctx.http.clone(), intervals, task_shutdown.clone());
+            }
             Box::pin(async move {
                 Ok(Data {
                     pool,
+                    comment_source,
                     config,
                     guide_text,
+                    active_wizards: Default::default(),
                 })
             })
         })
         .options(poise::FrameworkOptions {
             // configure framework here
             on_error: |err, ctx| Box::pin(error_handler(err, ctx)),
+            pre_command: |ctx| Box::pin(pre_command(ctx)),
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: Some(">>'".into()),
                 edit_tracker: Some(poise::EditTracker::for_timespan(Duration::from_secs(3600))),
@@ -914,14 +1494,37 @@ async fn main() {
         .command(new_token(), |f| f)
         .command(clear_token(), |f| f)
         .command(force_token(), |f| f)
+        .command(verify(), |f| f)
         .command(set_comment(), |f| f)
         .command(set_comment_b(), |f| f)
         .command(test_check(), |f| f)
         .command(test_verify(), |f| f)
         .command(sync_members(), |f| f)
         .command(status(), |f| f)
+        .command(language(), |f| f)
+        .command(my_data(), |f| f)
         .command(statusu(), |f| f)
+        .command(events(), |f| f)
         .command(set_role(), |f| f)
-        .run().await.unwrap();
+        .command(add_role(), |f| f)
+        .command(remove_role(), |f| f)
+        .command(list_roles(), |f| f)
+        .command(server_settings(), |f| f)
+        .command(set_guide(), |f| f)
+        .command(clear_guide(), |f| f)
+        .command(member_report(), |f| f)
+        .run();
+    
+    // dropping the framework closes the gateway connections
+    tokio::select! {
+        res = framework => res.unwrap(),
+        _ = shutdown::wait_for_signal() => info!("shutdown requested"),
+    }
+    
+    shutdown_controller.trigger();
+    drop(shutdown);
+    shutdown_controller.wait_for_tasks().await;
+    task_pool.close().await;
+    info!("shutdown complete");
 }
 
@@ -0,0 +1,132 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, TextEncoder,
+    Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge,
+};
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::{ TcpListener, TcpStream };
+use tracing::{ info, warn };
+use crate::shutdown::Shutdown;
+
+lazy_static! {
+    /// Comment checks performed by update_verification
+    pub static ref CHECKS: IntCounter = register_int_counter!(
+        "gentei_checks_total",
+        "Comment checks performed"
+    ).unwrap();
+    
+    /// Check results by MembershipStatus variant, or the CheckError kind
+    pub static ref CHECK_RESULTS: IntCounterVec = register_int_counter_vec!(
+        "gentei_check_results_total",
+        "Check results by membership status",
+        &["status"]
+    ).unwrap();
+    
+    /// UserError kinds from verifications
+    pub static ref HUMAN_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "gentei_verification_errors_total",
+        "Verification errors shown to users",
+        &["error"]
+    ).unwrap();
+    
+    pub static ref SCRAPER_LATENCY: HistogramVec = register_histogram_vec!(
+        "gentei_scraper_seconds",
+        "Time taken to load a comment",
+        &["backend"],
+        vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 40.0, 80.0]
+    ).unwrap();
+    
+    /// Scraper failures by CheckError kind, "exit" for a non zero exit of the subprocess
+    pub static ref SCRAPER_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "gentei_scraper_failures_total",
+        "Failed comment loads",
+        &["backend", "kind"]
+    ).unwrap();
+    
+    pub static ref RATE_LIMIT_WAIT: Histogram = register_histogram!(
+        "gentei_rate_limit_wait_seconds",
+        "Time spent waiting on the youtube rate limit",
+        vec![0.01, 0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0]
+    ).unwrap();
+    
+    /// Role changes by action ("add", "remove") and result ("ok", "err")
+    pub static ref ROLE_CHANGES: IntCounterVec = register_int_counter_vec!(
+        "gentei_role_changes_total",
+        "Discord role changes",
+        &["action", "result"]
+    ).unwrap();
+    
+    pub static ref PENDING_BACKLOG: IntGauge = register_int_gauge!(
+        "gentei_pending_verifications",
+        "Users due for a re-check"
+    ).unwrap();
+}
+
+pub fn record_role_change<T, E>(action: &str, res: &Result<T, E>) {
+    let result = if res.is_ok() { "ok" } else { "err" };
+    ROLE_CHANGES.with_label_values(&[action, result]).inc();
+}
+
+/// Serve `/metrics` in the prometheus text format on `addr` if it is set, until shutdown
+pub fn spawn_server(addr: Option<String>, shutdown: Shutdown) {
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return,
+    };
+    
+    tokio::spawn(async move {
+        if let Err(err) = serve(&addr, shutdown).await {
+            warn!(error = ?err, "metrics server error");
+        }
+    });
+}
+
+pub async fn serve(addr: &str, mut shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(addr, "serving metrics");
+    
+    loop {
+        let (mut socket, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.requested() => {
+                info!("metrics server stopped");
+                return Ok(());
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(err) = handle_request(&mut socket).await {
+                warn!(error = ?err, "metrics request error");
+            }
+        });
+    }
+}
+
+async fn handle_request(socket: &mut TcpStream) -> Result<(), anyhow::Error> {
+    let mut buf = [0u8; 1024];
+    let n = socket.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    
+    // only the path of the request line matters
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    
+    let encoder = TextEncoder::new();
+    let (status, content_type, body) = if path == "/metrics" {
+        let mut body = Vec::new();
+        encoder.encode(&prometheus::gather(), &mut body)?;
+        ("200 OK", encoder.format_type(), body)
+    } else {
+        ("404 Not Found", "text/plain", b"not found".to_vec())
+    };
+    
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len(),
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(&body).await?;
+    socket.shutdown().await?;
+    
+    Ok(())
+}
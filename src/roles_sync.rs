@@ -2,15 +2,19 @@
 use std::collections::{ BTreeMap, BTreeSet };
 use anyhow::{ Context as _, anyhow };
 use serde::{ Deserialize };
-use chrono::{ Utc };
+use chrono::{ DateTime, Utc, TimeZone };
+use chrono::naive::NaiveDateTime;
 
 use poise::serenity::model::id::{ GuildId, RoleId };
+use poise::serenity::model::guild::Member;
 use poise::serenity::http::Http;
+use tracing::{ info, warn, Instrument as _ };
 use sqlx::types::Json;
 use sqlx::PgPool;
 
 // use crate::Context;
-use crate::util::{ from_i, to_i };
+use crate::util::{ from_i, to_i, csv_field };
+use crate::metrics;
 
 #[derive(Debug, Deserialize)]
 #[serde(transparent)]
@@ -18,6 +22,221 @@ struct Roles {
     roles: BTreeMap<String, String>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+struct RoleTiers {
+    tiers: BTreeMap<String, i64>,
+}
+
+/// Give (`add`) or take away a role, recorded in the metrics and logged in a `role_change` span
+pub async fn change_role(
+    http: &Http,
+    member: &mut Member,
+    role_id: RoleId,
+    add: bool,
+) -> Result<(), poise::serenity::Error> {
+    let action = if add { "add" } else { "remove" };
+    let span = tracing::info_span!(
+        "role_change",
+        action,
+        guild = member.guild_id.0,
+        user = member.user.id.0,
+        role = role_id.0,
+    );
+    
+    async move {
+        let res = if add {
+            member.add_role(http, role_id).await
+        } else {
+            member.remove_role(http, role_id).await
+        };
+        metrics::record_role_change(action, &res);
+        match &res {
+            Ok(()) => info!("role changed"),
+            Err(err) => warn!(error = ?err, "role change failed"),
+        }
+        res
+    }
+        .instrument(span)
+        .await
+}
+
+/// Roles of a server grouped by youtube channel, with the minimum tier for each role
+pub fn channel_roles(
+    roles: &BTreeMap<String, String>,
+    tiers: &BTreeMap<String, i64>,
+) -> anyhow::Result<BTreeMap<String, Vec<(RoleId, i64)>>> {
+    let mut out: BTreeMap<String, Vec<(RoleId, i64)>> = BTreeMap::new();
+    for (role_id_str, yt_channel_id) in roles.iter() {
+        let role_id = RoleId(role_id_str.parse()?);
+        let min_tier = tiers.get(role_id_str).copied().unwrap_or(0);
+        out.entry(yt_channel_id.clone()).or_default().push((role_id, min_tier));
+    }
+    Ok(out)
+}
+
+/// Roles a member with `tier` should hold out of the roles for one channel
+///
+/// Only the highest qualifying role is given, `None` is a non member.
+pub fn qualifying_roles(roles: &[(RoleId, i64)], tier: Option<i64>) -> BTreeSet<RoleId> {
+    let tier = match tier {
+        Some(tier) => tier,
+        None => return BTreeSet::new(),
+    };
+    
+    let best = roles.iter()
+        .filter(|(_, min_tier)| *min_tier <= tier)
+        .map(|(_, min_tier)| *min_tier)
+        .max();
+    
+    roles.iter()
+        .filter(|(_, min_tier)| Some(*min_tier) == best)
+        .map(|(role_id, _)| *role_id)
+        .collect()
+}
+
+/// Role mappings for a server, `None` if it is not configured
+pub async fn get_channel_roles<'e, E>(
+    exec: E,
+    guild_id: u64,
+) -> anyhow::Result<Option<BTreeMap<String, Vec<(RoleId, i64)>>>>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Postgres>
+{
+    let res: Option<(Json<Roles>, Json<RoleTiers>)> = sqlx::query_as(r#"
+        SELECT roles, role_tiers
+        FROM genteib.servers
+        WHERE
+            server_id = $1
+    "#)
+        .bind(to_i(guild_id))
+        .fetch_optional(exec).await
+        .context("get server info")?;
+    
+    match res {
+        Some((Json(Roles{ roles }), Json(RoleTiers{ tiers }))) => Ok(Some(channel_roles(&roles, &tiers)?)),
+        None => Ok(None),
+    }
+}
+
+/// Map `role_id` to `yt_channel_id` for members with at least `min_tier` months
+pub async fn add_role_mapping(
+    pool: &PgPool,
+    server_id: u64,
+    role_id: u64,
+    yt_channel_id: &str,
+    min_tier: i64,
+) -> anyhow::Result<()> {
+    let role_id_str = role_id.to_string();
+    
+    let mut role_map = BTreeMap::new();
+    role_map.insert(&role_id_str, yt_channel_id);
+    let mut tier_map = BTreeMap::new();
+    tier_map.insert(&role_id_str, min_tier);
+    
+    sqlx::query(r#"
+        INSERT INTO genteib.servers (server_id, roles, role_tiers)
+        VALUES ($1, $2, $3)
+        ON CONFLICT ("server_id")
+            DO UPDATE SET
+                roles = servers.roles || EXCLUDED.roles,
+                role_tiers = servers.role_tiers || EXCLUDED.role_tiers
+    "#)
+        .bind(to_i(server_id))
+        .bind(Json(role_map))
+        .bind(Json(tier_map))
+        .execute(pool).await
+        .context("add role mapping")?;
+    
+    Ok(())
+}
+
+/// Remove the mapping for `role_id`, returns false if the role was not mapped
+pub async fn remove_role_mapping(
+    pool: &PgPool,
+    server_id: u64,
+    role_id: u64,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query(r#"
+        UPDATE genteib.servers
+        SET
+            roles = roles - $2,
+            role_tiers = role_tiers - $2
+        WHERE
+            server_id = $1 AND
+            roles ? $2
+    "#)
+        .bind(to_i(server_id))
+        .bind(role_id.to_string())
+        .execute(pool).await
+        .context("remove role mapping")?;
+    
+    Ok(res.rows_affected() > 0)
+}
+
+#[derive(Debug)]
+pub struct ServerSettings {
+    pub verify_expiry_hours: i64,
+    pub recheck_hours: i64,
+    pub max_failed_checks: i64,
+    /// language code for members without their own setting
+    pub locale: Option<String>,
+}
+
+/// Update the verification settings of a server, `None` keeps the current value
+pub async fn update_server_settings(
+    pool: &PgPool,
+    server_id: u64,
+    verify_expiry_hours: Option<i64>,
+    recheck_hours: Option<i64>,
+    max_failed_checks: Option<i64>,
+    locale: Option<&str>,
+) -> anyhow::Result<ServerSettings> {
+    let mut transaction = pool.begin().await?;
+    
+    sqlx::query(r#"
+        INSERT INTO genteib.servers (server_id)
+        VALUES ($1)
+        ON CONFLICT ("server_id")
+            DO NOTHING
+    "#)
+        .bind(to_i(server_id))
+        .execute(&mut transaction).await
+        .context("insert server")?;
+    
+    let (verify_expiry_hours, recheck_hours, max_failed_checks, locale): (i64, i64, i64, Option<String>) = sqlx::query_as(r#"
+        UPDATE genteib.servers
+            SET
+                verify_expiry = COALESCE($2 * INTERVAL '1 hour', verify_expiry),
+                recheck_interval = COALESCE($3 * INTERVAL '1 hour', recheck_interval),
+                max_failed_checks = COALESCE($4, max_failed_checks),
+                locale = COALESCE($5, locale)
+            WHERE
+                server_id = $1
+        RETURNING
+            (extract(epoch from verify_expiry) / 3600)::bigint,
+            (extract(epoch from recheck_interval) / 3600)::bigint,
+            max_failed_checks,
+            locale
+    "#)
+        .bind(to_i(server_id))
+        .bind(verify_expiry_hours)
+        .bind(recheck_hours)
+        .bind(max_failed_checks)
+        .bind(locale)
+        .fetch_one(&mut transaction).await
+        .context("update server settings")?;
+    
+    transaction.commit().await.context("transaction commit")?;
+    
+    Ok(ServerSettings {
+        verify_expiry_hours,
+        recheck_hours,
+        max_failed_checks,
+        locale,
+    })
+}
+
 pub async fn sync_roles(
     // ctx: &Context<'_>,
     pool: &PgPool,
@@ -41,57 +260,45 @@ pub async fn sync_roles(
     // let mut transaction = ctx.data().pool.begin().await?;
     let mut transaction = pool.begin().await?;
     
-    let res: Option<(Json<Roles>, )> = sqlx::query_as(r#"
-        SELECT roles
-        FROM genteib.servers
-        WHERE
-            server_id = $1
-    "#)
-        .bind(to_i(guild_id.0))
-        .fetch_optional(&mut transaction).await
-        .context("get server info")?;
-    
-    // dbg!(&res);
-    let roles = match res {
-        Some((Json(Roles{ roles }),)) => roles,
+    let channels = match get_channel_roles(&mut transaction, guild_id.0).await? {
+        Some(channels) => channels,
         None => return Err(anyhow!("server not configured {}", guild_id.0)),
     };
     
-    for (role_id_str, yt_channel_id) in roles.iter() {
-        let role_id = RoleId(role_id_str.parse()?);
-        
-        let verified: Vec<(i64,)> = sqlx::query_as(r#"
-            SELECT discord_id
+    for (yt_channel_id, roles) in channels.iter() {
+        let verified: Vec<(i64, i64)> = sqlx::query_as(r#"
+            SELECT discord_id, max(COALESCE(member_tier, 0))
             FROM genteib.users
             WHERE
                 yt_channel_id = $2 AND
-                $1 - last_verified < INTERVAL '3 days'
+                $1 - last_verified < (SELECT verify_expiry FROM genteib.servers WHERE server_id = $3)
+            GROUP BY discord_id
         "#)
             .bind(sync_time)
             .bind(yt_channel_id)
+            .bind(to_i(guild_id.0))
             .fetch_all(&mut transaction).await
             .context("get verified users")?;
-        let is_verified = {
-            let mut set = BTreeSet::new();
-            for (discord_id,) in verified {
-                set.insert(from_i(discord_id));
+        let verified_tiers = {
+            let mut map = BTreeMap::new();
+            for (discord_id, tier) in verified {
+                map.insert(from_i(discord_id), tier);
             }
-            set
+            map
         };
         
         for member in guild_members.iter_mut() {
-            let verified = is_verified.contains(&member.user.id.0);
-            let has_role = member.roles.contains(&role_id);
+            let tier = verified_tiers.get(&member.user.id.0).copied();
+            let wanted = qualifying_roles(roles, tier);
             
-            if verified && !has_role {
-                if let Err(err) = member.add_role(http, role_id).await {
-                    println!("error adding role {:?}", err);
-                };
-            }
-            if !verified && has_role {
-                if let Err(err) = member.remove_role(http, role_id).await {
-                    println!("error removing role {:?}", err);
-                };
+            for (role_id, _) in roles.iter() {
+                let want_role = wanted.contains(role_id);
+                let has_role = member.roles.contains(role_id);
+                
+                if want_role != has_role {
+                    // failures are logged by change_role
+                    let _ = change_role(http, member, *role_id, want_role).await;
+                }
             }
         }
         
@@ -109,3 +316,123 @@ pub async fn sync_roles(
     
     Ok(())
 }
+
+/// A guild member holding a mapped role, with their verification for the role's channel
+#[derive(Debug)]
+pub struct MemberReportRow {
+    pub discord_id: u64,
+    pub discord_name: String,
+    pub role_id: RoleId,
+    pub yt_channel_id: String,
+    /// `None` when the member has the role but no configured row for the channel
+    pub yt_channel_n: Option<i64>,
+    pub channel_name: Option<String>,
+    pub last_verified: Option<DateTime<Utc>>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub failed_checks: Option<i64>,
+}
+
+impl MemberReportRow {
+    pub const CSV_HEADER: &'static str = "discord_id,discord_name,role_id,yt_channel_id,yt_channel_n,channel_name,last_verified,last_checked,failed_checks";
+    
+    pub fn to_csv(&self) -> String {
+        let fmt_date = |d: &Option<DateTime<Utc>>| d.map(|d| d.to_rfc3339()).unwrap_or_default();
+        let fields = [
+            self.discord_id.to_string(),
+            self.discord_name.clone(),
+            self.role_id.0.to_string(),
+            self.yt_channel_id.clone(),
+            self.yt_channel_n.map(|n| n.to_string()).unwrap_or_default(),
+            self.channel_name.clone().unwrap_or_default(),
+            fmt_date(&self.last_verified),
+            fmt_date(&self.last_checked),
+            self.failed_checks.map(|n| n.to_string()).unwrap_or_default(),
+        ];
+        fields.iter()
+            .map(|f| csv_field(f))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Every member of a guild that holds a mapped role
+pub async fn member_report(
+    pool: &PgPool,
+    http: &Http,
+    guild_id: u64,
+) -> anyhow::Result<Vec<MemberReportRow>> {
+    let channels = match get_channel_roles(pool, guild_id).await? {
+        Some(channels) => channels,
+        None => return Err(anyhow!("server not configured {}", guild_id)),
+    };
+    
+    use poise::serenity::futures::TryStreamExt;
+    let guild_members: Vec<_> =
+        GuildId(guild_id).members_iter(http)
+            .try_collect().await?;
+    
+    let yt_channel_ids: Vec<&str> = channels.keys().map(|c| c.as_str()).collect();
+    let rows: Vec<(i64, String, i64, Option<NaiveDateTime>, Option<NaiveDateTime>, i64, Option<String>)> = sqlx::query_as(r#"
+        SELECT
+            discord_id, yt_channel_id, yt_channel_n,
+            last_verified, last_checked, failed_checks,
+            extra->>'channel_name'
+        FROM genteib.users
+        WHERE
+            yt_channel_id = ANY ($1)
+        ORDER BY yt_channel_n
+    "#)
+        .bind(&yt_channel_ids)
+        .fetch_all(pool).await
+        .context("get user rows")?;
+    
+    let mut user_rows: BTreeMap<(u64, &str), Vec<_>> = BTreeMap::new();
+    for (discord_id, yt_channel_id, yt_channel_n, last_verified, last_checked, failed_checks, channel_name) in rows.iter() {
+        user_rows.entry((from_i(*discord_id), yt_channel_id.as_str()))
+            .or_default()
+            .push((yt_channel_n, last_verified, last_checked, failed_checks, channel_name));
+    }
+    
+    let mut out = Vec::new();
+    for member in guild_members.iter() {
+        for (yt_channel_id, roles) in channels.iter() {
+            for (role_id, _) in roles.iter() {
+                if !member.roles.contains(role_id) {
+                    continue;
+                }
+                
+                let base = MemberReportRow {
+                    discord_id: member.user.id.0,
+                    discord_name: member.user.tag(),
+                    role_id: *role_id,
+                    yt_channel_id: yt_channel_id.clone(),
+                    yt_channel_n: None,
+                    channel_name: None,
+                    last_verified: None,
+                    last_checked: None,
+                    failed_checks: None,
+                };
+                
+                match user_rows.get(&(member.user.id.0, yt_channel_id.as_str())) {
+                    Some(rows) => {
+                        for (yt_channel_n, last_verified, last_checked, failed_checks, channel_name) in rows {
+                            out.push(MemberReportRow {
+                                yt_channel_n: Some(**yt_channel_n),
+                                channel_name: (*channel_name).clone(),
+                                last_verified: last_verified.map(|d| Utc.from_utc_datetime(&d)),
+                                last_checked: last_checked.map(|d| Utc.from_utc_datetime(&d)),
+                                failed_checks: Some(**failed_checks),
+                                yt_channel_id: base.yt_channel_id.clone(),
+                                discord_name: base.discord_name.clone(),
+                                ..base
+                            });
+                        }
+                    }
+                    None => out.push(base),
+                }
+            }
+        }
+    }
+    
+    Ok(out)
+}
@@ -0,0 +1,405 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+use anyhow::{ anyhow, Context as _ };
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use toml::Value;
+
+/// Config file read when `gentei_config` is not set, it is fine for it to be missing
+const DEFAULT_PATH: &str = "gentei.toml";
+
+lazy_static! {
+    /// Loaded on first use, main forces it at startup so a bad config fails early
+    pub static ref SETTINGS: Settings = match load() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("invalid config: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub discord: Discord,
+    pub database: Database,
+    pub youtube: Youtube,
+    #[serde(default)]
+    pub check: Check,
+    #[serde(default)]
+    pub service: Service,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
+    pub guide: Guide,
+    #[serde(default)]
+    pub logging: Logging,
+    #[serde(default)]
+    pub channel_cache: ChannelCache,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Discord {
+    pub auth: Secret,
+    pub owners: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Database {
+    pub url: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Youtube {
+    pub goojf: Secret,
+    /// channel and video users comment their token on
+    pub token_channel: String,
+    pub token_video: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// scrape youtube directly
+    Native,
+    /// run `program` with `args`
+    Subprocess,
+    /// scripted results from `fake_file`
+    Fake,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Check {
+    pub backend: Backend,
+    pub program: String,
+    pub args: Vec<String>,
+    pub fake_file: Option<PathBuf>,
+    /// requests allowed to each upstream host, shared by all concurrent checks
+    pub requests_per_second: u32,
+    /// hours a token stays usable when it has not proven ownership yet, 0 never expires
+    pub token_expiry_hours: u64,
+    /// `args` given as one string, only kept to reject it in `validate`
+    #[serde(skip)]
+    legacy_args: Option<String>,
+}
+
+impl Default for Check {
+    fn default() -> Self {
+        Check {
+            backend: Backend::Native,
+            program: "python".into(),
+            args: vec!["./comment_scrapper/downloader.py".into()],
+            fake_file: None,
+            requests_per_second: 2,
+            token_expiry_hours: 7 * 24,
+            legacy_args: None,
+        }
+    }
+}
+
+/// Background job timings, all in seconds
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Service {
+    pub verify_interval: u64,
+    pub sync_interval: u64,
+    pub over_paired_interval: u64,
+    /// checks run at the same time by `verify_pending`
+    pub verify_concurrency: usize,
+    /// how long an in-flight check may run after shutdown was requested
+    pub shutdown_grace: u64,
+}
+
+impl Default for Service {
+    fn default() -> Self {
+        Service {
+            verify_interval: 2,
+            sync_interval: 6 * 60 * 60,
+            over_paired_interval: 60 * 60,
+            verify_concurrency: 4,
+            shutdown_grace: 20,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metrics {
+    /// address to serve `/metrics` on, disabled if unset
+    pub addr: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Guide {
+    pub support_text: String,
+}
+
+/// Video to channel id lookups, see `youtube_req::get_channel_id`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelCache {
+    /// entries kept in memory, the database keeps all of them
+    pub size: usize,
+    /// seconds before a video is looked up again
+    pub ttl: u64,
+}
+
+impl Default for ChannelCache {
+    fn default() -> Self {
+        ChannelCache {
+            size: 1000,
+            ttl: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Logging {
+    /// tracing filter directives, like "info" or "warn,gentei_but_jank=debug"
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Logging {
+            level: "info".into(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretSource {
+    Value(String),
+    File { file: PathBuf },
+}
+
+/// Given inline or as `{ file = "path" }`, kept out of Debug output
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "SecretSource")]
+pub struct Secret(String);
+
+impl TryFrom<SecretSource> for Secret {
+    type Error = String;
+    
+    fn try_from(source: SecretSource) -> Result<Self, Self::Error> {
+        match source {
+            SecretSource::Value(value) => Ok(Secret(value)),
+            SecretSource::File { file } => {
+                let value = std::fs::read_to_string(&file)
+                    .map_err(|err| format!("read secret file {}: {}", file.display(), err))?;
+                Ok(Secret(value.trim_end_matches(&['\r', '\n'][..]).to_string()))
+            }
+        }
+    }
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+#[derive(Clone, Copy)]
+enum EnvKind {
+    Str,
+    Int,
+    /// comma separated ids
+    Ids,
+    /// toml array, `check_args='["./downloader.py", "--flag"]'`
+    ///
+    /// Anything not starting with `[` is kept as a string for `validate` to reject.
+    Array,
+    /// also read from a file named by `<var>_file`
+    Secret,
+}
+
+/// Environment variables overriding `[section] key` in the config file
+const ENV_OVERRIDES: &[(&str, &str, &str, EnvKind)] = &[
+    ("discord_auth", "discord", "auth", EnvKind::Secret),
+    ("owners", "discord", "owners", EnvKind::Ids),
+    ("pg_url", "database", "url", EnvKind::Secret),
+    ("goojf", "youtube", "goojf", EnvKind::Secret),
+    ("token_channel", "youtube", "token_channel", EnvKind::Str),
+    ("token_video", "youtube", "token_video", EnvKind::Str),
+    ("check_backend", "check", "backend", EnvKind::Str),
+    ("check_program", "check", "program", EnvKind::Str),
+    ("check_args", "check", "args", EnvKind::Array),
+    ("check_fake_file", "check", "fake_file", EnvKind::Str),
+    ("check_requests_per_second", "check", "requests_per_second", EnvKind::Int),
+    ("token_expiry_hours", "check", "token_expiry_hours", EnvKind::Int),
+    ("verify_interval", "service", "verify_interval", EnvKind::Int),
+    ("sync_interval", "service", "sync_interval", EnvKind::Int),
+    ("over_paired_interval", "service", "over_paired_interval", EnvKind::Int),
+    ("verify_concurrency", "service", "verify_concurrency", EnvKind::Int),
+    ("shutdown_grace", "service", "shutdown_grace", EnvKind::Int),
+    ("metrics_addr", "metrics", "addr", EnvKind::Str),
+    ("support_text", "guide", "support_text", EnvKind::Str),
+    ("log_level", "logging", "level", EnvKind::Str),
+    ("log_format", "logging", "format", EnvKind::Str),
+    ("channel_cache_size", "channel_cache", "size", EnvKind::Int),
+    ("channel_cache_ttl", "channel_cache", "ttl", EnvKind::Int),
+];
+
+fn env_value(var: &str, raw: &str, kind: EnvKind) -> Result<Value, anyhow::Error> {
+    Ok(match kind {
+        EnvKind::Str | EnvKind::Secret => Value::String(raw.to_string()),
+        EnvKind::Int => Value::Integer(raw.trim().parse()
+            .with_context(|| format!("{} is not a number", var))?),
+        EnvKind::Ids => Value::Array(raw.split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse().map(Value::Integer))
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("{} is not a comma separated list of ids", var))?),
+        EnvKind::Array if !raw.trim_start().starts_with('[') => Value::String(raw.to_string()),
+        EnvKind::Array => {
+            let mut parsed: toml::value::Table = toml::from_str(&format!("v = {}", raw))
+                .with_context(|| format!("{} is not a toml array like [\"a\", \"b\"]", var))?;
+            match parsed.remove("v") {
+                Some(value @ Value::Array(_)) => value,
+                _ => return Err(anyhow!("{} is not a toml array like [\"a\", \"b\"]", var)),
+            }
+        }
+    })
+}
+
+fn apply_env(root: &mut toml::value::Table) -> Result<(), anyhow::Error> {
+    for &(var, section, key, kind) in ENV_OVERRIDES {
+        let value = if let Ok(raw) = std::env::var(var) {
+            env_value(var, &raw, kind)?
+        } else if let (EnvKind::Secret, Ok(path)) = (kind, std::env::var(format!("{}_file", var))) {
+            let mut table = toml::value::Table::new();
+            table.insert("file".into(), Value::String(path));
+            Value::Table(table)
+        } else {
+            continue
+        };
+        
+        let section_table = root.entry(section)
+            .or_insert_with(|| Value::Table(Default::default()));
+        match section_table {
+            Value::Table(table) => {
+                table.insert(key.into(), value);
+            }
+            _ => return Err(anyhow!("{} is not a table", section)),
+        }
+    }
+    Ok(())
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        let mut errors = Vec::new();
+        
+        if self.discord.auth.expose().is_empty() {
+            errors.push("discord.auth is empty".to_string());
+        }
+        if self.discord.owners.is_empty() {
+            errors.push("discord.owners needs at least one id".to_string());
+        }
+        let token_channel = &self.youtube.token_channel;
+        if !(token_channel.starts_with("UC") && token_channel.len() == 24) {
+            errors.push(format!("youtube.token_channel {:?} is not a channel id", token_channel));
+        }
+        if self.youtube.token_video.len() != 11 {
+            errors.push(format!("youtube.token_video {:?} is not a video id", self.youtube.token_video));
+        }
+        match self.check.backend {
+            Backend::Subprocess if self.check.program.is_empty() => {
+                errors.push("check.program is required for the subprocess backend".to_string());
+            }
+            Backend::Fake if self.check.fake_file.is_none() => {
+                errors.push("check.fake_file is required for the fake backend".to_string());
+            }
+            _ => (),
+        }
+        if let Some(legacy) = &self.check.legacy_args {
+            let args: Vec<_> = legacy.split("  ")
+                .filter(|x| !x.is_empty())
+                .map(|x| format!("{:?}", x))
+                .collect();
+            errors.push(format!(
+                "check.args {:?} uses the old form separated by two spaces, write it as a toml array instead: check_args='[{}]'",
+                legacy, args.join(", "),
+            ));
+        }
+        if self.service.verify_interval == 0 {
+            errors.push("service.verify_interval must be at least 1".to_string());
+        }
+        if self.service.verify_concurrency == 0 {
+            errors.push("service.verify_concurrency must be at least 1".to_string());
+        }
+        if self.check.requests_per_second == 0 {
+            errors.push("check.requests_per_second must be at least 1".to_string());
+        }
+        if self.channel_cache.size == 0 {
+            errors.push("channel_cache.size must be at least 1".to_string());
+        }
+        if let Err(err) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!("logging.level {:?}: {}", self.logging.level, err));
+        }
+        
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("\n")))
+        }
+    }
+}
+
+/// Read the config file, apply environment overrides and validate
+///
+/// The file is `gentei_config` if set, otherwise `gentei.toml` when it exists.
+pub fn load() -> Result<Settings, anyhow::Error> {
+    let (path, required) = match std::env::var("gentei_config") {
+        Ok(path) => (PathBuf::from(path), true),
+        Err(_) => (PathBuf::from(DEFAULT_PATH), false),
+    };
+    
+    let mut root: toml::value::Table = match std::fs::read_to_string(&path) {
+        Ok(text) => toml::from_str(&text)
+            .with_context(|| format!("parse {}", path.display()))?,
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+        Err(err) => return Err(err).with_context(|| format!("read {}", path.display())),
+    };
+    
+    apply_env(&mut root)?;
+    
+    // the old string form, taken out so `validate` can say how to convert it
+    let legacy_args = match root.get_mut("check") {
+        Some(Value::Table(check)) if matches!(check.get("args"), Some(Value::String(_))) => {
+            check.remove("args").and_then(|args| args.as_str().map(String::from))
+        }
+        _ => None,
+    };
+    
+    let mut settings: Settings = Value::Table(root).try_into()?;
+    settings.check.legacy_args = legacy_args;
+    settings.validate()?;
+    
+    Ok(settings)
+}
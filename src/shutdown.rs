@@ -0,0 +1,86 @@
+use std::time::Duration;
+use tokio::sync::{ mpsc, watch };
+
+/// Cooperative shutdown signal handed to background jobs
+///
+/// `ShutdownController::wait_for_tasks` returns once every clone has been dropped.
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: watch::Receiver<bool>,
+    grace: Duration,
+    _alive: mpsc::Sender<()>,
+}
+
+pub struct ShutdownController {
+    requested: watch::Sender<bool>,
+    alive: mpsc::Receiver<()>,
+}
+
+/// `grace` is how long an in-flight check may run after shutdown was requested
+pub fn channel(grace: Duration) -> (ShutdownController, Shutdown) {
+    let (requested_tx, requested_rx) = watch::channel(false);
+    let (alive_tx, alive_rx) = mpsc::channel(1);
+    
+    let controller = ShutdownController {
+        requested: requested_tx,
+        alive: alive_rx,
+    };
+    let shutdown = Shutdown {
+        requested: requested_rx,
+        grace,
+        _alive: alive_tx,
+    };
+    (controller, shutdown)
+}
+
+/// Resolves on SIGTERM or ctrl-c
+pub async fn wait_for_signal() {
+    use tokio::signal::unix::{ signal, SignalKind };
+    let mut term = signal(SignalKind::terminate()).expect("install SIGTERM handler");
+    
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = term.recv() => (),
+    }
+}
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+    
+    /// Resolves once shutdown has been requested
+    pub async fn requested(&mut self) {
+        while !*self.requested.borrow() {
+            if self.requested.changed().await.is_err() {
+                // controller dropped, nothing can request shutdown anymore
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+    
+    /// Resolves when the grace period after a shutdown request has run out
+    pub async fn deadline(&mut self) {
+        self.requested().await;
+        tokio::time::sleep(self.grace).await;
+    }
+    
+    /// Sleep for `dur`, returns false if interrupted by a shutdown request
+    pub async fn sleep(&mut self, dur: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(dur) => true,
+            _ = self.requested() => false,
+        }
+    }
+}
+
+impl ShutdownController {
+    pub fn trigger(&self) {
+        let _ = self.requested.send(true);
+    }
+    
+    /// Wait until every `Shutdown` handle is dropped
+    pub async fn wait_for_tasks(mut self) {
+        while self.alive.recv().await.is_some() {}
+    }
+}
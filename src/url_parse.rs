@@ -5,25 +5,93 @@ pub fn is_url(maybe_url: &str) -> bool {
     maybe_url.contains(":") && maybe_url.contains("/")
 }
 
-pub fn extract_video_comment_id(video_url: &str) -> Option<(String, Option<String>)> {
+/// Prepend `https://` to youtube links pasted without a scheme, like `youtube.com/@name`
+pub fn with_scheme(maybe_url: &str) -> std::borrow::Cow<'_, str> {
+    let has_host = ["youtube.com/", "youtu.be/"].iter()
+        .any(|host| maybe_url.contains(host));
+    if has_host && !maybe_url.contains("://") {
+        format!("https://{}", maybe_url).into()
+    } else {
+        maybe_url.into()
+    }
+}
+
+/// Canonical channel ids are `UC` followed by 22 url safe base64 characters
+pub fn is_channel_id(id: &str) -> bool {
+    id.len() == 24 &&
+        id.starts_with("UC") &&
+        id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Which form of youtube link a video id came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoLinkKind {
+    /// youtu.be/<id>
+    ShortLink,
+    /// /watch?v=<id>, also on m. and music.
+    Watch,
+    /// /embed/<id>
+    Embed,
+    /// /shorts/<id>
+    Shorts,
+    /// /live/<id>
+    Live,
+    /// /v/<id>
+    V,
+    /// /attribution_link?u=<one of the above>
+    AttributionLink,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoLink {
+    pub kind: VideoLinkKind,
+    pub video_id: String,
+    /// `lc` query parameter, set when the link points at a comment
+    pub comment_id: Option<String>,
+}
+
+const YOUTUBE_HOSTS: &[&str] = &["www.youtube.com", "youtube.com", "m.youtube.com", "music.youtube.com"];
+
+fn query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// The only path segment left, ignoring a trailing slash
+fn last_segment<'a>(mut segs: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let res = segs.next().filter(|s| !s.is_empty())?;
+    match segs.next() {
+        None | Some("") => Some(res),
+        Some(_) => None,
+    }
+}
+
+pub fn extract_video_link(video_url: &str) -> Option<VideoLink> {
     let url = Url::parse(video_url).ok()?;
-    
+    video_link(&url, true)
+}
+
+fn video_link(url: &Url, follow_attribution: bool) -> Option<VideoLink> {
     let host = url.host()?;
     let host = match host {
         Host::Domain(host) => host,
         _ => return None,
     };
     
+    let comment_id = query_param(url, "lc");
+    let link = |kind, video_id: &str| Some(VideoLink {
+        kind,
+        video_id: video_id.into(),
+        comment_id: comment_id.clone(),
+    });
+    
     if host == "youtu.be" {
-        let mut segs = url.path_segments()?;
-        let res = segs.next()?;
-        if segs.next().is_some() {
-            return None
-        }
-        return Some((res.into(), None));
+        let video_id = last_segment(url.path_segments()?)?;
+        return link(VideoLinkKind::ShortLink, video_id);
     }
     
-    if !&["www.youtube.com", "youtube.com"].contains(&host) {
+    if !YOUTUBE_HOSTS.contains(&host) {
         return None
     }
     
@@ -31,33 +99,107 @@ pub fn extract_video_comment_id(video_url: &str) -> Option<(String, Option<Strin
     let seg = segs.next()?;
     
     match seg {
-        "embed" => {
-            let res = segs.next()?;
-            if segs.next().is_some() {
-                return None
-            }
-            Some((res.into(), None))
-        }
+        "embed" => link(VideoLinkKind::Embed, last_segment(segs)?),
+        "shorts" => link(VideoLinkKind::Shorts, last_segment(segs)?),
+        "live" => link(VideoLinkKind::Live, last_segment(segs)?),
+        "v" => link(VideoLinkKind::V, last_segment(segs)?),
         "watch" => {
-            if segs.next().is_some() {
+            if last_segment(segs).is_some() {
                 return None
             }
-            let mut video_id = None;
-            let mut comment_id = None;
-            for (k, v) in url.query_pairs() {
-                if k == "v" {
-                    video_id = Some(v.into())
-                } else if k == "lc" {
-                    comment_id = Some(v.into())
-                }
-            }
-            return video_id.map(|v| (v, comment_id))
+            link(VideoLinkKind::Watch, &query_param(url, "v")?)
+        }
+        "attribution_link" if follow_attribution => {
+            // u is the path of the real link, like /watch?v=...&feature=share
+            let base = Url::parse("https://www.youtube.com").unwrap();
+            let inner = base.join(&query_param(url, "u")?).ok()?;
+            let inner = video_link(&inner, false)?;
+            Some(VideoLink {
+                kind: VideoLinkKind::AttributionLink,
+                video_id: inner.video_id,
+                comment_id: inner.comment_id.or(comment_id),
+            })
         }
         _ => None
     }
 }
 
-pub fn extract_channel_id(channel_url: &str) -> Option<String> {
+/// Community post, `/post/<id>` or `/channel/<id>/community?lb=<id>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostLink {
+    pub post_id: String,
+    /// `lc` query parameter, set when the link points at a comment
+    pub comment_id: Option<String>,
+}
+
+pub fn extract_post_link(post_url: &str) -> Option<PostLink> {
+    let url = Url::parse(post_url).ok()?;
+    
+    let host = match url.host()? {
+        Host::Domain(host) => host,
+        _ => return None,
+    };
+    if !YOUTUBE_HOSTS.contains(&host) {
+        return None
+    }
+    
+    let segs: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let post_id = match segs.as_slice() {
+        ["post", post_id] => post_id.to_string(),
+        [.., "community"] => query_param(&url, "lb")?,
+        _ => return None,
+    };
+    
+    Some(PostLink {
+        post_id,
+        comment_id: query_param(&url, "lc"),
+    })
+}
+
+/// The ways a channel shows up in urls, only `Id` can be used without a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelRef {
+    /// /channel/UC...
+    Id(String),
+    /// /@handle, stored without the @
+    Handle(String),
+    /// /c/Name
+    Custom(String),
+    /// /user/Name
+    User(String),
+}
+
+impl ChannelRef {
+    /// Page to read the channel id from
+    pub fn url(&self) -> String {
+        match self {
+            ChannelRef::Id(id) => format!("https://www.youtube.com/channel/{}", id),
+            ChannelRef::Handle(handle) => format!("https://www.youtube.com/@{}", handle),
+            ChannelRef::Custom(name) => format!("https://www.youtube.com/c/{}", name),
+            ChannelRef::User(name) => format!("https://www.youtube.com/user/{}", name),
+        }
+    }
+    
+    /// Key in genteib.channel_aliases, youtube treats these names case insensitively
+    pub fn alias(&self) -> String {
+        match self {
+            ChannelRef::Id(id) => id.clone(),
+            ChannelRef::Handle(handle) => format!("@{}", handle.to_lowercase()),
+            ChannelRef::Custom(name) => format!("c/{}", name.to_lowercase()),
+            ChannelRef::User(name) => format!("user/{}", name.to_lowercase()),
+        }
+    }
+}
+
+/// Channel from a youtube channel url, or a bare `@handle`
+pub fn extract_channel_ref(channel_url: &str) -> Option<ChannelRef> {
+    if let Some(handle) = channel_url.strip_prefix('@') {
+        if handle.is_empty() || handle.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return None
+        }
+        return Some(ChannelRef::Handle(handle.into()));
+    }
+    
     let url = Url::parse(channel_url).ok()?;
     
     let host = url.host()?;
@@ -66,15 +208,126 @@ pub fn extract_channel_id(channel_url: &str) -> Option<String> {
         _ => return None,
     };
     
-    if !&["www.youtube.com", "youtube.com"].contains(&host) {
+    if !&["www.youtube.com", "youtube.com", "m.youtube.com"].contains(&host) {
         return None
     }
     
     let mut segs = url.path_segments()?;
+    let seg = segs.next()?;
     
-    if segs.next()? != "channel" {
-        return None
+    if let Some(handle) = seg.strip_prefix('@') {
+        if handle.is_empty() {
+            return None
+        }
+        return Some(ChannelRef::Handle(handle.into()));
+    }
+    
+    let name = segs.next().filter(|s| !s.is_empty())?;
+    match seg {
+        "channel" => Some(ChannelRef::Id(name.into())),
+        "c" => Some(ChannelRef::Custom(name.into())),
+        "user" => Some(ChannelRef::User(name.into())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn video(url: &str) -> Option<(VideoLinkKind, String, Option<String>)> {
+        extract_video_link(url).map(|link| (link.kind, link.video_id, link.comment_id))
+    }
+    
+    #[test]
+    fn video_links() {
+        let id = || "dQw4w9WgXcQ".to_string();
+        let lc = || Some("UgzAbc123".to_string());
+        
+        assert_eq!(video("https://www.youtube.com/watch?v=dQw4w9WgXcQ&lc=UgzAbc123"), Some((VideoLinkKind::Watch, id(), lc())));
+        assert_eq!(video("https://m.youtube.com/watch?v=dQw4w9WgXcQ"), Some((VideoLinkKind::Watch, id(), None)));
+        assert_eq!(video("https://music.youtube.com/watch?v=dQw4w9WgXcQ"), Some((VideoLinkKind::Watch, id(), None)));
+        assert_eq!(video("https://youtu.be/dQw4w9WgXcQ?lc=UgzAbc123"), Some((VideoLinkKind::ShortLink, id(), lc())));
+        assert_eq!(video("https://www.youtube.com/embed/dQw4w9WgXcQ"), Some((VideoLinkKind::Embed, id(), None)));
+        assert_eq!(video("https://www.youtube.com/shorts/dQw4w9WgXcQ/?lc=UgzAbc123"), Some((VideoLinkKind::Shorts, id(), lc())));
+        assert_eq!(video("https://www.youtube.com/live/dQw4w9WgXcQ"), Some((VideoLinkKind::Live, id(), None)));
+        assert_eq!(video("https://www.youtube.com/v/dQw4w9WgXcQ"), Some((VideoLinkKind::V, id(), None)));
+        assert_eq!(
+            video("https://www.youtube.com/attribution_link?u=%2Fwatch%3Fv%3DdQw4w9WgXcQ%26lc%3DUgzAbc123"),
+            Some((VideoLinkKind::AttributionLink, id(), lc())),
+        );
+    }
+    
+    #[test]
+    fn not_video_links() {
+        assert_eq!(video("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(video("https://www.youtube.com/watch"), None);
+        assert_eq!(video("https://www.youtube.com/watch/extra?v=dQw4w9WgXcQ"), None);
+        assert_eq!(video("https://www.youtube.com/shorts/dQw4w9WgXcQ/extra"), None);
+        assert_eq!(video("https://www.youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx"), None);
+        assert_eq!(video("dQw4w9WgXcQ"), None);
     }
     
-    segs.next().map(|s| s.into())
+    #[test]
+    fn post_links() {
+        assert_eq!(extract_post_link("https://www.youtube.com/post/UgkxAbc?lc=UgzDef"), Some(PostLink {
+            post_id: "UgkxAbc".into(),
+            comment_id: Some("UgzDef".into()),
+        }));
+        assert_eq!(extract_post_link("https://www.youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx/community?lb=UgkxAbc"), Some(PostLink {
+            post_id: "UgkxAbc".into(),
+            comment_id: None,
+        }));
+        assert_eq!(extract_post_link("https://www.youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx/community"), None);
+        assert_eq!(extract_post_link("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(extract_post_link("https://example.com/post/UgkxAbc"), None);
+    }
+    
+    #[test]
+    fn channel_refs() {
+        assert_eq!(extract_channel_ref("@name"), Some(ChannelRef::Handle("name".into())));
+        assert_eq!(extract_channel_ref("https://www.youtube.com/@name"), Some(ChannelRef::Handle("name".into())));
+        assert_eq!(extract_channel_ref("https://youtube.com/@name/videos"), Some(ChannelRef::Handle("name".into())));
+        assert_eq!(
+            extract_channel_ref("https://www.youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx"),
+            Some(ChannelRef::Id("UCxxxxxxxxxxxxxxxxxxxxxx".into())),
+        );
+        assert_eq!(extract_channel_ref("https://m.youtube.com/c/Name"), Some(ChannelRef::Custom("Name".into())));
+        assert_eq!(extract_channel_ref("https://www.youtube.com/user/Name/"), Some(ChannelRef::User("Name".into())));
+        
+        assert_eq!(extract_channel_ref("@"), None);
+        assert_eq!(extract_channel_ref("@two words"), None);
+        assert_eq!(extract_channel_ref("https://www.youtube.com/@"), None);
+        assert_eq!(extract_channel_ref("https://www.youtube.com/channel/"), None);
+        assert_eq!(extract_channel_ref("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(extract_channel_ref("https://example.com/@name"), None);
+    }
+    
+    #[test]
+    fn channel_ref_aliases() {
+        assert_eq!(ChannelRef::Handle("Name".into()).alias(), "@name");
+        assert_eq!(ChannelRef::Custom("Name".into()).alias(), "c/name");
+        assert_eq!(ChannelRef::User("Name".into()).url(), "https://www.youtube.com/user/Name");
+    }
+    
+    #[test]
+    fn links_without_scheme() {
+        assert_eq!(with_scheme("youtube.com/@name"), "https://youtube.com/@name");
+        assert_eq!(with_scheme("www.youtube.com/watch?v=dQw4w9WgXcQ"), "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(with_scheme("youtu.be/dQw4w9WgXcQ"), "https://youtu.be/dQw4w9WgXcQ");
+        assert_eq!(with_scheme("https://youtube.com/@name"), "https://youtube.com/@name");
+        assert_eq!(with_scheme("@name"), "@name");
+        assert_eq!(with_scheme("UCxxxxxxxxxxxxxxxxxxxxxx"), "UCxxxxxxxxxxxxxxxxxxxxxx");
+        
+        assert_eq!(extract_channel_ref(&with_scheme("youtube.com/@name")), Some(ChannelRef::Handle("name".into())));
+    }
+    
+    #[test]
+    fn channel_ids() {
+        assert!(is_channel_id("UCxxxxxxxxxxxxxxxxxxxx-_"));
+        assert!(!is_channel_id("UCshort"));
+        assert!(!is_channel_id("ABxxxxxxxxxxxxxxxxxxxxxx"));
+        assert!(!is_channel_id("UCxxxxxxxxxxxxxxxxxxxxx!"));
+        assert!(!is_channel_id("youtube.com/@name"));
+    }
 }
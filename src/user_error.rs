@@ -0,0 +1,204 @@
+use std::fmt;
+
+use crate::guide;
+use crate::locale::{ self, Locale };
+
+/// How much the user has to worry about an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// a step is missing, nothing is broken
+    Info,
+    /// the user can fix it themselves
+    Warning,
+    /// needs a server admin, or a new token
+    Error,
+}
+
+impl Severity {
+    pub fn label(&self, locale: Locale) -> String {
+        locale.text(match self {
+            Severity::Info => "severity.info",
+            Severity::Warning => "severity.warning",
+            Severity::Error => "severity.error",
+        })
+    }
+}
+
+/// Everything shown to users when a command or verification fails
+///
+/// Attach as anyhow context, `error_handler` and the daemon DMs render it with
+/// `render`. Texts are in the locale catalogs under `error.<kind>` and
+/// `hint.<kind>`. Display is the english explanation, which is also what
+/// verification events store.
+#[derive(Debug, Clone)]
+pub enum UserError {
+    // verification
+    NotAMember,
+    CouldNotLoadComment,
+    TokenNotInComment,
+    /// a `gentei:` token that is cut off or has changed characters
+    TokenMangled,
+    TokenExpired,
+    WrongChannel {
+        correct: String,
+        actual: String,
+    },
+    UserNotConfigured,
+    CommentNotSet,
+    TooManyFailures,
+    OverPairedDiscordId,
+    
+    // command arguments
+    InvalidChannelN,
+    NotAChannel,
+    ChannelLookupFailed,
+    PostLinkWithoutComment,
+    VideoLinkWithoutComment,
+    NotACommentLink,
+    VideoLookupFailed,
+    PostLookupFailed,
+    /// membership comment link in `verify` is the token comment
+    MembershipOnTokenChannel,
+    NegativeTier,
+    IntervalTooShort,
+    NegativeMaxFailedChecks,
+    /// `remove_role` argument is neither a role mention nor an id
+    NotARole(String),
+    /// from `poise::ArgumentParseError`
+    InvalidArgument(String),
+    UnknownLanguage(String),
+    /// `guide` server argument matches no server with a guide the user is in
+    UnknownGuideServer(String),
+    GuideEmpty,
+    GuideSectionTooLong {
+        /// 1-based
+        section: usize,
+    },
+    TooManyGuideSections,
+    
+    // server setup
+    GuildOnly,
+    RoleNotInServer,
+    RoleNotAssignable,
+    MissingManageRoles,
+    RoleAboveBot,
+    ServerNotConfigured,
+}
+
+impl UserError {
+    /// Variant name for metrics and the catalog keys
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UserError::NotAMember => "not_a_member",
+            UserError::CouldNotLoadComment => "could_not_load_comment",
+            UserError::TokenNotInComment => "token_not_in_comment",
+            UserError::TokenMangled => "token_mangled",
+            UserError::TokenExpired => "token_expired",
+            UserError::WrongChannel{ .. } => "wrong_channel",
+            UserError::UserNotConfigured => "user_not_configured",
+            UserError::CommentNotSet => "comment_not_set",
+            UserError::TooManyFailures => "too_many_failures",
+            UserError::OverPairedDiscordId => "over_paired_discord_id",
+            UserError::InvalidChannelN => "invalid_channel_n",
+            UserError::NotAChannel => "not_a_channel",
+            UserError::ChannelLookupFailed => "channel_lookup_failed",
+            UserError::PostLinkWithoutComment => "post_link_without_comment",
+            UserError::VideoLinkWithoutComment => "video_link_without_comment",
+            UserError::NotACommentLink => "not_a_comment_link",
+            UserError::VideoLookupFailed => "video_lookup_failed",
+            UserError::PostLookupFailed => "post_lookup_failed",
+            UserError::MembershipOnTokenChannel => "membership_on_token_channel",
+            UserError::NegativeTier => "negative_tier",
+            UserError::IntervalTooShort => "interval_too_short",
+            UserError::NegativeMaxFailedChecks => "negative_max_failed_checks",
+            UserError::NotARole(_) => "not_a_role",
+            UserError::InvalidArgument(_) => "invalid_argument",
+            UserError::UnknownLanguage(_) => "unknown_language",
+            UserError::UnknownGuideServer(_) => "unknown_guide_server",
+            UserError::GuideEmpty => "guide_empty",
+            UserError::GuideSectionTooLong{ .. } => "guide_section_too_long",
+            UserError::TooManyGuideSections => "too_many_guide_sections",
+            UserError::GuildOnly => "guild_only",
+            UserError::RoleNotInServer => "role_not_in_server",
+            UserError::RoleNotAssignable => "role_not_assignable",
+            UserError::MissingManageRoles => "missing_manage_roles",
+            UserError::RoleAboveBot => "role_above_bot",
+            UserError::ServerNotConfigured => "server_not_configured",
+        }
+    }
+    
+    pub fn severity(&self) -> Severity {
+        match self {
+            UserError::UserNotConfigured
+            | UserError::CommentNotSet
+            | UserError::GuildOnly => Severity::Info,
+            
+            UserError::TooManyFailures
+            | UserError::OverPairedDiscordId
+            | UserError::MissingManageRoles
+            | UserError::RoleAboveBot
+            | UserError::ServerNotConfigured => Severity::Error,
+            
+            _ => Severity::Warning,
+        }
+    }
+    
+    /// Placeholders of the catalog entries
+    fn args(&self) -> Vec<(&'static str, String)> {
+        match self {
+            UserError::WrongChannel{ correct, actual } => vec![("correct", correct.clone()), ("actual", actual.clone())],
+            UserError::NotARole(role) => vec![("role", role.clone())],
+            UserError::InvalidArgument(message) => vec![("message", message.clone())],
+            UserError::UnknownLanguage(code) => vec![
+                ("code", code.clone()),
+                ("available", Locale::ALL.iter().map(|l| l.code()).collect::<Vec<_>>().join(", ")),
+            ],
+            UserError::UnknownGuideServer(server) => vec![("server", server.clone())],
+            UserError::GuideSectionTooLong{ section } => vec![
+                ("section", section.to_string()),
+                ("max", guide::MAX_SECTION_LEN.to_string()),
+            ],
+            UserError::TooManyGuideSections => vec![("max", guide::MAX_SECTIONS.to_string())],
+            _ => Vec::new(),
+        }
+    }
+    
+    fn localized(&self, locale: Locale, key: &str) -> String {
+        let args = self.args();
+        let args: Vec<(&str, &dyn fmt::Display)> = args.iter()
+            .map(|(name, value)| (*name, value as &dyn fmt::Display))
+            .collect();
+        locale.fmt(key, &args)
+    }
+    
+    /// From `error.<kind>` in the locale catalog
+    pub fn explanation(&self, locale: Locale) -> String {
+        self.localized(locale, &format!("error.{}", self.kind()))
+    }
+    
+    /// What to do about it from `hint.<kind>`, guide steps are the numbered steps of the `guide` command
+    pub fn hint(&self, locale: Locale) -> Option<String> {
+        let key = format!("hint.{}", self.kind());
+        locale.get(&key)?;
+        Some(self.localized(locale, &key))
+    }
+    
+    /// Explanation followed by the hint
+    pub fn with_hint(&self, locale: Locale) -> String {
+        match self.hint(locale) {
+            Some(hint) => format!("{}. {}", self.explanation(locale), hint),
+            None => self.explanation(locale),
+        }
+    }
+    
+    /// `warning: Comment does not contain token. Comment the token ...`
+    pub fn render(&self, locale: Locale) -> String {
+        format!("{}: {}", self.severity().label(locale), self.with_hint(locale))
+    }
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.explanation(locale::DEFAULT))
+    }
+}
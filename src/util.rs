@@ -1,4 +1,5 @@
 
+use std::borrow::Cow;
 use rand::Rng;
 
 pub fn to_i(x: u64) -> i64 {
@@ -9,28 +10,86 @@ pub fn from_i(x: i64) -> u64 {
     u64::from_be_bytes(x.to_be_bytes())
 }
 
-// const TOKEN_CHARS: Vec<char> = {
-//     // &['a'..'z', 'A'..'Z']
-//     let mut acc = Vec::new();
-//     acc.extend('a'..'z');
-    
-//     // &['a']
-//     acc
-// };
+/// Quote a field for csv output if it contains separators or quotes
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Start of tokens from `gen_token`, older tokens are 32 letters without it
+pub const TOKEN_PREFIX: &str = "gentei:";
+/// Letters and digits without the easily confused l, o, I, O, 0 and 1
+const TOKEN_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const TOKEN_BODY_LEN: usize = 24;
+
+/// Two characters of position weighted sum, catches any single changed character and swapped neighbours
+fn token_checksum(body: &str) -> Option<String> {
+    let n = TOKEN_ALPHABET.len();
+    let mut sum = 0;
+    for (i, c) in body.bytes().enumerate() {
+        let value = TOKEN_ALPHABET.iter().position(|&x| x == c)?;
+        sum = (sum + (i + 1) * (value + 1)) % (n * n);
+    }
+    Some([TOKEN_ALPHABET[sum / n] as char, TOKEN_ALPHABET[sum % n] as char].iter().collect())
+}
 
+/// `gentei:` followed by 24 random characters and a 2 character checksum
 pub fn gen_token() -> String {
-    let mut acc = Vec::new();
-    acc.extend('a'..'z');
-    acc.extend('A'..'Z');
-    let slice = rand::distributions::Slice::new(&acc).unwrap();
+    let slice = rand::distributions::Slice::new(TOKEN_ALPHABET).unwrap();
     
     let rng = rand::thread_rng();
-    let token: String = rng
+    let body: String = rng
         .sample_iter(&slice)
-        .take(32)
+        .take(TOKEN_BODY_LEN)
+        .map(|&c| c as char)
         .collect();
+    let checksum = token_checksum(&body).expect("token body from alphabet");
     
-    token
+    format!("{}{}{}", TOKEN_PREFIX, body, checksum)
+}
+
+/// Whether `token` is a complete token from `gen_token` with a correct checksum
+pub fn token_is_valid(token: &str) -> bool {
+    let rest = match token.strip_prefix(TOKEN_PREFIX) {
+        Some(rest) if rest.len() == TOKEN_BODY_LEN + 2 && rest.is_ascii() => rest,
+        _ => return false,
+    };
+    let (body, checksum) = rest.split_at(TOKEN_BODY_LEN);
+    token_checksum(body).as_deref() == Some(checksum)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenMatch {
+    Found,
+    /// a prefixed token that is cut off or has changed characters
+    Mangled,
+    Absent,
+}
+
+/// Look for `token` in comment text
+///
+/// Another complete token is `Absent` rather than `Mangled`, it is most likely
+/// one from an earlier `new_token`.
+pub fn find_token(text: &str, token: &str) -> TokenMatch {
+    if text.contains(token) {
+        return TokenMatch::Found;
+    }
+    
+    let mangled = text.match_indices(TOKEN_PREFIX).any(|(start, _)| {
+        let candidate: String = text[start..].chars()
+            .skip(TOKEN_PREFIX.len())
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        !token_is_valid(&format!("{}{}", TOKEN_PREFIX, candidate))
+    });
+    if mangled {
+        TokenMatch::Mangled
+    } else {
+        TokenMatch::Absent
+    }
 }
 
 const UUID_CONTEXT: uuid::v1::Context = uuid::v1::Context::new(0);
@@ -53,3 +112,80 @@ pub fn gen_uuid() -> String {
     
     id_str
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn generated_tokens_are_valid() {
+        for _ in 0..100 {
+            let token = gen_token();
+            assert!(token.starts_with(TOKEN_PREFIX));
+            assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_BODY_LEN + 2);
+            assert!(token_is_valid(&token), "{}", token);
+        }
+    }
+    
+    #[test]
+    fn checksum_catches_changes() {
+        let body = "abcdefghijkmnpqrstuvwxyz";
+        let checksum = token_checksum(body).unwrap();
+        
+        // every single changed character
+        for i in 0..body.len() {
+            for &c in TOKEN_ALPHABET {
+                if body.as_bytes()[i] == c {
+                    continue;
+                }
+                let mut changed = body.as_bytes().to_vec();
+                changed[i] = c;
+                let changed = String::from_utf8(changed).unwrap();
+                assert_ne!(token_checksum(&changed).unwrap(), checksum, "{}", changed);
+            }
+        }
+        
+        // every swap of neighbours
+        for i in 0..body.len() - 1 {
+            let mut swapped = body.as_bytes().to_vec();
+            swapped.swap(i, i + 1);
+            let swapped = String::from_utf8(swapped).unwrap();
+            assert_ne!(token_checksum(&swapped).unwrap(), checksum, "{}", swapped);
+        }
+        
+        // characters outside the alphabet
+        assert_eq!(token_checksum("abc0"), None);
+    }
+    
+    #[test]
+    fn invalid_tokens() {
+        let token = gen_token();
+        assert!(!token_is_valid(&token[..token.len() - 1]));
+        assert!(!token_is_valid(&token[TOKEN_PREFIX.len()..]));
+        assert!(!token_is_valid(&format!("{}a", token)));
+        assert!(!token_is_valid(&token.replace(TOKEN_PREFIX, "gentel:")));
+    }
+    
+    #[test]
+    fn find_tokens() {
+        let token = gen_token();
+        let other = gen_token();
+        
+        assert_eq!(find_token(&format!("my token {} thanks", token), &token), TokenMatch::Found);
+        assert_eq!(find_token(&token, &token), TokenMatch::Found);
+        
+        // cut off
+        assert_eq!(find_token(&format!("token {}", &token[..token.len() - 3]), &token), TokenMatch::Mangled);
+        // a changed character
+        let mut changed = token.clone().into_bytes();
+        let last = changed.len() - 3;
+        changed[last] = if changed[last] == b'a' { b'b' } else { b'a' };
+        let changed = String::from_utf8(changed).unwrap();
+        assert_eq!(find_token(&changed, &token), TokenMatch::Mangled);
+        
+        // another complete token is most likely an old one
+        assert_eq!(find_token(&other, &token), TokenMatch::Absent);
+        assert_eq!(find_token("no token here", &token), TokenMatch::Absent);
+        assert_eq!(find_token("", &token), TokenMatch::Absent);
+    }
+}
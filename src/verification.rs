@@ -1,5 +1,5 @@
 
-use std::fmt;
+use std::collections::BTreeSet;
 use anyhow::anyhow;
 use anyhow::Context;
 use chrono::{ DateTime, Utc, TimeZone };
@@ -16,47 +16,17 @@ use poise::serenity::model::id::RoleId;
 // use sqlx::Transaction;
 // use sqlx::Postgres;
 use sqlx::{ PgPool };
-use crate::util::{to_i, from_i};
+use crate::util::{to_i, from_i, find_token, TokenMatch};
+use crate::settings::SETTINGS;
+use futures::stream::{ self, StreamExt };
+use tracing::{ warn, error };
+use crate::metrics;
+use crate::shutdown::Shutdown;
+use crate::roles_sync::{ get_channel_roles, qualifying_roles, change_role };
 
-use crate::check_wrapper::{check_member, Member, Not, NotFound};
-
-#[derive(Debug)]
-pub enum HumanContext {
-    NotAMember,
-    CouldNotLoadComment,
-    TokenNotInComment,
-    WrongChannel {
-        correct: String,
-        actual: String,
-    },
-    UserNotConfigured,
-    CommentNotSet,
-    TooManyFailures,
-    OverPairedDiscordId,
-}
-
-impl fmt::Display for HumanContext {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            HumanContext::NotAMember =>
-                write!(f, "Not a member"),
-            HumanContext::CouldNotLoadComment =>
-                write!(f, "Could not load video or comment"),
-            HumanContext::TokenNotInComment =>
-                write!(f, "Comment does not contain token"),
-            HumanContext::WrongChannel{ correct, actual } =>
-                write!(f, "Comment is not on the correct channel {} != (actual){}", correct, actual),
-            HumanContext::UserNotConfigured =>
-                write!(f, "No token has been created for channel"),
-            HumanContext::CommentNotSet =>
-                write!(f, "No comment set for channel"),
-            HumanContext::TooManyFailures =>
-                write!(f, "Too many consecutive failures"),
-            HumanContext::OverPairedDiscordId =>
-                write!(f, "Too many discord ids paired to youtube account"),
-        }
-    }
-}
+use crate::user_error::UserError;
+use crate::locale::Locale;
+use crate::check_wrapper::{CheckError, CommentSource, CommentTarget, Member, Not, NotFound};
 
 pub async fn check_over_paired_discord_ids(pool: &PgPool) -> Result<Vec<String>, anyhow::Error> {
     let rows = sqlx::query!(r#"
@@ -92,16 +62,23 @@ pub async fn check_over_paired_discord_ids(pool: &PgPool) -> Result<Vec<String>,
     Ok(user_chan_ids)
 }
 
-pub async fn verify_pending(pool: &PgPool, n: usize) -> Result<Vec<VerifyResult>, anyhow::Error> {
+/// Check up to `n` pending users, running `concurrency` checks at a time
+///
+/// A failed check is logged and left out of the results, the rest of the batch
+/// carries on. No new checks start once shutdown is requested, and a check
+/// still running when the shutdown grace period ends is rolled back.
+pub async fn verify_pending(pool: &PgPool, source: &dyn CommentSource, n: usize, concurrency: usize, shutdown: &Shutdown) -> Result<Vec<VerifyResult>, anyhow::Error> {
+    let (backlog,): (i64,) = sqlx::query_as(r#"
+        SELECT count(*)
+        FROM genteib.pending_verifications
+    "#)
+        .fetch_one(pool).await
+        .context("count pending")?;
+    metrics::PENDING_BACKLOG.set(backlog);
+    
     let pending: Vec<(i64, String, i64)> = sqlx::query_as(r#"
         SELECT discord_id, yt_channel_id, yt_channel_n
-        FROM genteib.users
-        WHERE
-            yt_video_id IS NOT NULL AND
-            yt_comment_id IS NOT NULL AND
-            failed_checks <= 2 AND
-            current_timestamp - last_checked > INTERVAL '2 days' AND
-            current_timestamp - last_verified > INTERVAL '2 days'
+        FROM genteib.pending_verifications
         LIMIT $1
     "#)
         .bind(n as i32)
@@ -109,13 +86,32 @@ pub async fn verify_pending(pool: &PgPool, n: usize) -> Result<Vec<VerifyResult>
         .context("get pending")?;
     
     // dbg!(&pending);
-    let mut results = Vec::new();
-    for (discord_id, yt_channel_id, yt_channel_n) in pending {
-        let discord_id = from_i(discord_id);
-        let res = update_verification(pool, discord_id, &yt_channel_id, yt_channel_n).await
-            .context(format!("update_verification {} {}", discord_id, yt_channel_id))?;
-        results.push(res);
-    }
+    let results = stream::iter(pending)
+        .map(|(discord_id, yt_channel_id, yt_channel_n)| async move {
+            if shutdown.is_requested() {
+                return None;
+            }
+            
+            let discord_id = from_i(discord_id);
+            let mut deadline = shutdown.clone();
+            let res = tokio::select! {
+                res = update_verification(pool, source, discord_id, &yt_channel_id, yt_channel_n) => res,
+                _ = deadline.deadline() => {
+                    warn!(discord_id, %yt_channel_id, "shutdown grace period ended during check, rolled back");
+                    return None;
+                }
+            };
+            match res {
+                Ok(res) => Some(res),
+                Err(err) => {
+                    error!(discord_id, %yt_channel_id, error = ?err, "update_verification failed");
+                    None
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(|res| async move { res })
+        .collect::<Vec<_>>().await;
     
     Ok(results)
 }
@@ -128,8 +124,12 @@ pub struct VerifyResult {
     pub channel_name: String,
     pub was_member: bool,
     pub is_member: bool,
+    /// membership tier before this check
+    pub was_tier: Option<i64>,
+    /// membership tier, `None` if not a member
+    pub tier: Option<i64>,
     pub ownership_verified: bool,
-    pub errors: Vec<HumanContext>,
+    pub errors: Vec<UserError>,
 }
 
 #[derive(Debug)]
@@ -146,17 +146,24 @@ impl VerifyResult {
         self.was_member && !self.is_member
     }
     
-    async fn set_role(&self, http: &Http, guild_id: u64, role_id: u64) -> Result<(), anyhow::Error> {
+    pub fn tier_changed(&self) -> bool {
+        self.was_member && self.is_member && self.was_tier.unwrap_or(0) != self.tier.unwrap_or(0)
+    }
+    
+    /// Add or remove roles in a guild so the user holds exactly `wanted` out of `roles`
+    async fn set_roles(&self, http: &Http, guild_id: u64, roles: &[(RoleId, i64)], wanted: &BTreeSet<RoleId>) -> Result<(), anyhow::Error> {
         let guild_id = GuildId(guild_id);
-        let role_id = RoleId(role_id);
         let user_id = UserId(self.discord_id);
         let guild = Guild::get(http, guild_id).await?;
         let mut member = guild.member(http, user_id).await?;
         
-        if self.became_member() {
-            member.add_role(http, role_id).await?;
-        } else if self.became_non_member() {
-            member.remove_role(http, role_id).await?;
+        for (role_id, _) in roles {
+            let want_role = wanted.contains(role_id);
+            let has_role = member.roles.contains(role_id);
+            
+            if want_role != has_role {
+                change_role(http, &mut member, *role_id, want_role).await?;
+            }
         }
         
         Ok(())
@@ -165,27 +172,37 @@ impl VerifyResult {
     pub async fn update_roles(&self, pool: &PgPool, http: &Http) -> Result<Option<UpdateRolesResult>, anyhow::Error> {
         let add = self.became_member();
         let rem = self.became_non_member();
-        if !(add || rem) {
+        if !(add || rem || self.tier_changed()) {
             return Ok(None)
         }
         
-        // jsonb_path_query(roles, '($.keyvalue() ? (@.value == "UCV1xUwfM2v2oBtT3JNvic3w")).key') #>> '{}'
-        // select all roles that correspond to the given channel
-        let rows: Vec<(i64, String,)> = sqlx::query_as(&format!(r#"
-            SELECT
-                server_id, jsonb_path_query(roles, '($.keyvalue() ? (@.value == "{}")).key') #>> '{{}}'
+        // select all servers with roles that correspond to the given channel
+        let rows: Vec<(i64,)> = sqlx::query_as(r#"
+            SELECT server_id
             FROM genteib.servers
-        "#, self.yt_channel_id))
-            // .bind(&format!(r#"($.keyvalue() ? (@.value == "{}")).key"#, self.yt_channel_id))
+            WHERE
+                EXISTS (SELECT 1 FROM jsonb_each_text(roles) r WHERE r.value = $1)
+        "#)
+            .bind(&self.yt_channel_id)
             .fetch_all(pool).await
             .context("get roles")?;
         
         // dbg!(&rows);
         
         let mut errors = Vec::new();
-        for (guild_id, role_id) in rows {
-            let role_id: u64 = role_id.parse()?;
-            match self.set_role(http, from_i(guild_id), role_id).await {
+        for (guild_id,) in rows {
+            let guild_id = from_i(guild_id);
+            let channels = match get_channel_roles(pool, guild_id).await? {
+                Some(channels) => channels,
+                None => continue,
+            };
+            let roles = match channels.get(&self.yt_channel_id) {
+                Some(roles) => roles,
+                None => continue,
+            };
+            let wanted = qualifying_roles(roles, self.tier);
+            
+            match self.set_roles(http, guild_id, roles, &wanted).await {
                 Ok(()) => (),
                 Err(err) => {
                     // dbg!(&err);
@@ -200,62 +217,250 @@ impl VerifyResult {
     }
 }
 
-pub async fn update_verification<'c>(
+/// What an update_verification run saw, written to genteib.verification_events
+#[derive(Debug, Default)]
+struct VerificationEvent {
+    target: Option<CommentTarget>,
+    yt_comment_id: Option<String>,
+    status: Option<serde_json::Value>,
+    comment_text: Option<String>,
+    user_yt_channel_id: Option<String>,
+    was_member: bool,
+}
+
+async fn record_event(
+    pool: &PgPool,
+    user: u64, yt_channel_id: &str, yt_channel_n: i64,
+    event: &VerificationEvent,
+    res: &Result<VerifyResult, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let (errors, is_member, failure, failure_kind): (Vec<String>, _, _, _) = match res {
+        Ok(res) => (
+            res.errors.iter().map(|e| e.to_string()).collect(),
+            Some(res.is_member),
+            None,
+            None,
+        ),
+        Err(err) => (
+            err.downcast_ref::<UserError>().map(|e| e.to_string()).into_iter().collect(),
+            None,
+            Some(format!("{:?}", err)),
+            err.downcast_ref::<CheckError>().map(|e| e.kind()),
+        ),
+    };
+    
+    sqlx::query(r#"
+        INSERT INTO genteib.verification_events
+                (created, discord_id, yt_channel_id, yt_channel_n,
+                 yt_video_id, yt_comment_id, status, comment_text, user_yt_channel_id,
+                 errors, was_member, is_member, failure, yt_post_id, failure_kind)
+        VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+    "#)
+        .bind(Utc::now().naive_utc())
+        .bind(to_i(user))
+        .bind(yt_channel_id)
+        .bind(yt_channel_n)
+        .bind(event.target.as_ref().and_then(|t| t.video_id()))
+        .bind(&event.yt_comment_id)
+        .bind(event.status.as_ref().map(sqlx::types::Json))
+        .bind(&event.comment_text)
+        .bind(&event.user_yt_channel_id)
+        .bind(&errors)
+        .bind(event.was_member)
+        .bind(is_member)
+        .bind(failure)
+        .bind(event.target.as_ref().and_then(|t| t.post_id()))
+        .bind(failure_kind)
+        .execute(pool).await
+        .context("insert verification event")?;
+    
+    Ok(())
+}
+
+/// Wait before re-checking after `failures` transient failures in a row,
+/// doubling from a minute up to 6 hours
+fn transient_backoff(failures: i32) -> chrono::Duration {
+    let minutes = 1i64 << failures.clamp(0, 9);
+    std::cmp::min(chrono::Duration::minutes(minutes), chrono::Duration::hours(6))
+}
+
+#[tracing::instrument(skip(exec, source), fields(discord_id = user, channel = %yt_channel_id))]
+pub async fn update_verification(
+    exec: &PgPool,
+    source: &dyn CommentSource,
+    user: u64, yt_channel_id: &str, yt_channel_n: i64,
+) -> Result<VerifyResult, anyhow::Error> {
+    let mut event = VerificationEvent::default();
+    
+    let res = update_verification_inner(exec, source, user, yt_channel_id, yt_channel_n, &mut event).await;
+    
+    match &res {
+        Ok(res) => {
+            for err in res.errors.iter() {
+                metrics::HUMAN_ERRORS.with_label_values(&[err.kind()]).inc();
+            }
+        }
+        Err(err) => {
+            if let Some(err) = err.downcast_ref::<UserError>() {
+                metrics::HUMAN_ERRORS.with_label_values(&[err.kind()]).inc();
+            }
+        }
+    }
+    
+    if let Err(err) = record_event(exec, user, yt_channel_id, yt_channel_n, &event, &res).await {
+        warn!(error = ?err, "could not record verification event");
+    }
+    
+    res
+}
+
+fn token_error(token_match: TokenMatch) -> UserError {
+    match token_match {
+        TokenMatch::Mangled => UserError::TokenMangled,
+        TokenMatch::Found | TokenMatch::Absent => UserError::TokenNotInComment,
+    }
+}
+
+async fn update_verification_inner<'c>(
     // exec: &mut Transaction<'c, Postgres>,
     exec: &PgPool,
+    source: &dyn CommentSource,
     user: u64, yt_channel_id: &str, yt_channel_n: i64,
+    event: &mut VerificationEvent,
     // video_id: &str, comment_id: &str
 ) -> Result<VerifyResult, anyhow::Error>
     // where
     //     E: Executor<'c, Database = Postgres>
 {
-    let row: Option<(String, Option<String>, Option<String>, i64, Option<bool>)> = sqlx::query_as(r#"
-        SELECT "token", yt_video_id, yt_comment_id, failed_checks, (extra->'member_on_last_update')::bool
-        FROM genteib.users
+    // a token only matters until the user proved owning a youtube account
+    let token_expiry_hours = match SETTINGS.check.token_expiry_hours {
+        0 => None,
+        hours => Some(i32::try_from(hours).unwrap_or(i32::MAX)),
+    };
+    let row: Option<(String, Option<String>, Option<String>, Option<String>, i64, i64, i32, Option<bool>, Option<i64>, Option<bool>)> = sqlx::query_as(r#"
+        SELECT
+            u."token", u.yt_video_id, u.yt_post_id, u.yt_comment_id,
+            u.failed_checks, COALESCE(cs.max_failed_checks, 5), u.transient_failures,
+            (u.extra->'member_on_last_update')::bool, u.member_tier,
+            (
+                u.last_channel_verified IS NULL AND
+                u.last_verified IS NULL AND
+                u.token_created < current_timestamp - make_interval(hours => $4) AND
+                NOT EXISTS (
+                    SELECT 1
+                    FROM genteib.users o
+                    WHERE
+                        o.discord_id = $1 AND
+                        o.last_channel_verified IS NOT NULL
+                )
+            )
+        FROM genteib.users u
+        LEFT JOIN genteib.channel_settings cs ON cs.yt_channel_id = u.yt_channel_id
         WHERE
-            discord_id = $1 AND
-            yt_channel_id = $2 AND
-            yt_channel_n = $3
+            u.discord_id = $1 AND
+            u.yt_channel_id = $2 AND
+            u.yt_channel_n = $3
             -- yt_video_id = $2 AND
             -- comment_id = $3
     "#)
         .bind(to_i(user))
         .bind(yt_channel_id)
         .bind(yt_channel_n)
+        .bind(token_expiry_hours)
         .fetch_optional(&*exec).await
         .context("select")?;
     
-    let (token, video_id, comment_id, failed_checks, member_on_last_update) = row.ok_or_else(||
+    let (token, video_id, post_id, comment_id, failed_checks, max_failed_checks, transient_failures, member_on_last_update, was_tier, token_expired) = row.ok_or_else(||
         anyhow!(
             "could not find user {}({}) {}",
             user, to_i(user), yt_channel_id,
         )
-            .context(HumanContext::UserNotConfigured)
+            .context(UserError::UserNotConfigured)
     )?;
     
     let member_on_last_update = member_on_last_update.unwrap_or(false);
+    event.was_member = member_on_last_update;
+    let target = CommentTarget::from_columns(video_id, post_id);
+    event.target = target.clone();
+    event.yt_comment_id = comment_id.clone();
     
-    let (video_id, comment_id) = match (video_id, comment_id) {
-        (Some(v), Some(c)) => (v, c),
+    let (target, comment_id) = match (target, comment_id) {
+        (Some(t), Some(c)) => (t, c),
         _ => {
             let err = anyhow!(
                 "no comment set for user {}({}) {}",
                 user, to_i(user), yt_channel_id,
             )
-                .context(HumanContext::CommentNotSet);
+                .context(UserError::CommentNotSet);
             
             return Err(err)
         }
     };
     
-    if failed_checks > 5 {
-        let err = anyhow!("too many failures {}", failed_checks)
-            .context(HumanContext::TooManyFailures);
+    if failed_checks > max_failed_checks {
+        // the daemon skips the row until the next recheck interval, new_token resets the count
+        sqlx::query(r#"
+            UPDATE genteib.users
+                SET
+                    last_checked = $4
+                WHERE
+                    discord_id = $1 AND
+                    yt_channel_id = $2 AND
+                    yt_channel_n = $3
+        "#)
+            .bind(to_i(user))
+            .bind(yt_channel_id)
+            .bind(yt_channel_n)
+            .bind(Utc::now().naive_utc())
+            .execute(exec).await
+            .context("update last checked")?;
+        
+        let err = anyhow!("too many failures {} > {}", failed_checks, max_failed_checks)
+            .context(UserError::TooManyFailures);
+        return Err(err)
+    }
+    
+    if token_expired.unwrap_or(false) {
+        let err = anyhow!("token expired for user {}({}) {}", user, to_i(user), yt_channel_id)
+            .context(UserError::TokenExpired);
         return Err(err)
     }
     
     let verify_time = Utc::now();
     
+    // nothing is written before the fetch, a shutdown while it runs leaves the row as it was
+    metrics::CHECKS.inc();
+    let res = match source.fetch(&target, &comment_id).await {
+        Err(err) if err.is_transient() => {
+            metrics::CHECK_RESULTS.with_label_values(&[err.kind()]).inc();
+            // not the user's fault, try again later without counting it as a failed check
+            let retry_after = verify_time + transient_backoff(transient_failures);
+            sqlx::query(r#"
+                UPDATE genteib.users
+                    SET
+                        transient_failures = transient_failures + 1,
+                        retry_after = $4
+                    WHERE
+                        discord_id = $1 AND
+                        yt_channel_id = $2 AND
+                        yt_channel_n = $3
+            "#)
+                .bind(to_i(user))
+                .bind(yt_channel_id)
+                .bind(yt_channel_n)
+                .bind(retry_after.naive_utc())
+                .execute(exec).await
+                .context("update retry after")?;
+            
+            warn!(%retry_after, transient_failures = transient_failures + 1, error = %err, "transient check failure");
+            return Err(err.into());
+        }
+        res => res,
+    };
+    
+    // dropping the transaction (shutdown, crash) rolls back the failed_checks bump
+    let mut transaction = exec.begin().await?;
+    
     sqlx::query(r#"
         UPDATE genteib.users
             SET
@@ -270,17 +475,37 @@ pub async fn update_verification<'c>(
         .bind(yt_channel_id)
         .bind(yt_channel_n)
         .bind(verify_time.naive_utc())
-        .execute(&*exec).await
+        .execute(&mut transaction).await
         .context("update last checked")?;
     
-    let res = check_member(&video_id, &comment_id).await?;
+    let res = match res {
+        Ok(res) => res,
+        Err(err) => {
+            metrics::CHECK_RESULTS.with_label_values(&[err.kind()]).inc();
+            // a failed check still counts
+            transaction.commit().await.context("transaction commit")?;
+            return Err(err.into());
+        }
+    };
+    metrics::CHECK_RESULTS.with_label_values(&[res.1.kind()]).inc();
     
     let video_info = res.0;
     
+    event.status = Some(serde_json::to_value(&res.1).context("serialize status")?);
+    match &res.1 {
+        Member{ text, user_channel_id, .. } | Not{ text, user_channel_id, .. } => {
+            event.comment_text = Some(text.clone());
+            event.user_yt_channel_id = Some(user_channel_id.clone());
+        }
+        NotFound => (),
+    }
+    
     sqlx::query(r#"
         UPDATE genteib.users
             SET
-                extra = extra || $4
+                extra = extra || $4,
+                transient_failures = 0,
+                retry_after = NULL
             WHERE
                 discord_id = $1 AND
                 yt_channel_id = $2 AND
@@ -290,15 +515,19 @@ pub async fn update_verification<'c>(
         .bind(yt_channel_id)
         .bind(yt_channel_n)
         .bind(sqlx::types::Json(serde_json::json!({"channel_name": video_info.channel_name})))
-        .execute(&*exec).await
+        .execute(&mut transaction).await
         .context("update user channel id non member")?;
     
     let mut errors = Vec::new();
     let mut ownership_errors = Vec::new();
+    let mut badge_tier = None;
     
     let user_chan = match res.1 {
-        Member{ channel_id: actual_channel_id, text, user_channel_id } => {
-            let is_verified = if !text.contains(&token) {
+        Member{ channel_id: actual_channel_id, text, user_channel_id, tier, badge } => {
+            badge_tier = Some((tier, badge));
+            
+            let token_match = find_token(&text, &token);
+            let is_verified = if token_match != TokenMatch::Found {
                 let res: Option<_> = sqlx::query!(
                     r#"
                         SELECT true
@@ -312,7 +541,7 @@ pub async fn update_verification<'c>(
                     &user_channel_id,
                     to_i(user),
                 )
-                    .fetch_optional(&*exec).await
+                    .fetch_optional(&mut transaction).await
                     .context("select other verified comments")?;
                 
                 match res {
@@ -320,7 +549,7 @@ pub async fn update_verification<'c>(
                         false
                     }
                     None => {
-                        errors.push(HumanContext::TokenNotInComment);
+                        errors.push(token_error(token_match));
                         false
                         
                     }
@@ -330,7 +559,7 @@ pub async fn update_verification<'c>(
             };
             
             if actual_channel_id != yt_channel_id {
-                errors.push(HumanContext::WrongChannel { correct: yt_channel_id.into(), actual: actual_channel_id.clone() });
+                errors.push(UserError::WrongChannel { correct: yt_channel_id.into(), actual: actual_channel_id.clone() });
             }
             
             if is_verified {
@@ -340,21 +569,22 @@ pub async fn update_verification<'c>(
             }
         }
         Not{ text, user_channel_id, channel_id: actual_channel_id } => {
-            errors.push(HumanContext::NotAMember);
+            errors.push(UserError::NotAMember);
             
             if actual_channel_id != yt_channel_id {
-                errors.push(HumanContext::WrongChannel { correct: yt_channel_id.into(), actual: actual_channel_id.clone() });
+                errors.push(UserError::WrongChannel { correct: yt_channel_id.into(), actual: actual_channel_id.clone() });
             }
             
-            if text.contains(&token) {
-                Some(user_channel_id)
-            } else {
-                ownership_errors.push(HumanContext::TokenNotInComment);
-                None
+            match find_token(&text, &token) {
+                TokenMatch::Found => Some(user_channel_id),
+                token_match => {
+                    ownership_errors.push(token_error(token_match));
+                    None
+                }
             }
         }
         NotFound => {
-            errors.push(HumanContext::CouldNotLoadComment);
+            errors.push(UserError::CouldNotLoadComment);
             None
         }
     };
@@ -375,12 +605,12 @@ pub async fn update_verification<'c>(
                 &user_channel_id,
                 // to_i(user),
             )
-                .fetch_optional(&*exec).await
+                .fetch_optional(&mut transaction).await
                 .context("select other verified comments")?;
             
             match res {
                 Some(row) if row.n > 3 => {
-                    ownership_errors.push(HumanContext::OverPairedDiscordId);
+                    ownership_errors.push(UserError::OverPairedDiscordId);
                     None
                 }
                 Some(_) | None => Some(user_channel_id),
@@ -400,6 +630,8 @@ pub async fn update_verification<'c>(
         channel_name: video_info.channel_name,
         was_member: member_on_last_update,
         is_member: is_member,
+        was_tier,
+        tier: if is_member { badge_tier.as_ref().map(|(tier, _)| *tier) } else { None },
         ownership_verified: user_chan.is_some(),
         errors,
     };
@@ -413,6 +645,8 @@ pub async fn update_verification<'c>(
                     last_channel_verified = COALESCE($6, last_channel_verified),
                     user_yt_channel_id = COALESCE($5, user_yt_channel_id),
                     failed_checks = 0,
+                    member_tier = $7,
+                    member_badge = $8,
                     extra = extra || '{"member_on_last_update": true}'
                 WHERE
                     discord_id = $1 AND
@@ -425,8 +659,10 @@ pub async fn update_verification<'c>(
             verify_time.naive_utc(),
             user_chan.as_deref(),
             user_chan.as_ref().map(|_| verify_time.naive_utc()),
+            res.tier,
+            badge_tier.as_ref().map(|(_, badge)| badge.as_str()),
         )
-            .execute(&*exec).await
+            .execute(&mut transaction).await
             .context("update last verified")?;
     } else {
         sqlx::query!(r#"
@@ -436,6 +672,8 @@ pub async fn update_verification<'c>(
                     last_channel_verified = COALESCE($5, last_channel_verified),
                     user_yt_channel_id = COALESCE($4, user_yt_channel_id),
                     failed_checks = 0,
+                    member_tier = NULL,
+                    member_badge = NULL,
                     extra = extra || '{"member_on_last_update": false}'
                 WHERE
                     discord_id = $1 AND
@@ -449,17 +687,19 @@ pub async fn update_verification<'c>(
             user_chan.as_ref().map(|_| verify_time.naive_utc()),
         )
             // .bind(sqlx::types::Json(serde_json::json!({"channel_name": video_info.channel_name})))
-            .execute(&*exec).await
+            .execute(&mut transaction).await
             .context("update user channel id non member")?;
     }
     
+    transaction.commit().await.context("transaction commit")?;
+    
     Ok(res)
 }
 
 pub struct UserStatus {
     yt_channel_id: String,
     yt_channel_n: i64,
-    yt_video_id: Option<String>,
+    target: Option<CommentTarget>,
     yt_comment_id: Option<String>,
     token: String,
     last_verified: Option<DateTime<Utc>>,
@@ -469,6 +709,7 @@ pub struct UserStatus {
     is_verified: bool,
     channel_verified: bool,
     channel_name: Option<String>,
+    member_badge: Option<String>,
 }
 
 impl UserStatus {
@@ -476,7 +717,7 @@ impl UserStatus {
     //     self.yt_video_id.is_some() && self.yt_comment_id.is_some()
     // }
     
-    pub fn format_message(&self) -> String {
+    pub fn format_message(&self, locale: Locale) -> String {
         let mut out = String::new();
         use std::fmt::Write;
         
@@ -496,28 +737,32 @@ impl UserStatus {
             }
         }
         out.push('\n');
-        if let (Some(vid), Some(com)) = (self.yt_video_id.as_ref(), self.yt_comment_id.as_ref()) {
-            write!(out, "`  `<https://www.youtube.com/watch?v={}&lc={}>\n", vid, com).unwrap();
+        if let (Some(target), Some(com)) = (self.target.as_ref(), self.yt_comment_id.as_ref()) {
+            write!(out, "`  `<{}>\n", target.comment_url(com)).unwrap();
             write!(out, "```diff\n").unwrap();
         } else {
             write!(out, "```diff\n").unwrap();
-            write!(out, "- no comment set\n").unwrap();
+            write!(out, "- {}\n", locale.text("status.no_comment")).unwrap();
         }
         
-        write!(out, "  token: {}\n", self.token).unwrap();
+        write!(out, "  {}: {}\n", locale.text("status.token"), self.token).unwrap();
         
         if self.is_verified && self.channel_verified {
-            write!(out, "+ membership verified\n").unwrap();
+            write!(out, "+ {}\n", locale.text("status.membership_verified")).unwrap();
         } else if self.is_verified {
-            write!(out, "+ membership verified (no token)\n").unwrap();
+            write!(out, "+ {}\n", locale.text("status.membership_verified_no_token")).unwrap();
         } else if self.channel_verified {
-            write!(out, "+ channel verified\n").unwrap();
+            write!(out, "+ {}\n", locale.text("status.channel_verified")).unwrap();
         } else {
-            write!(out, "- not verified\n").unwrap();
+            write!(out, "- {}\n", locale.text("status.not_verified")).unwrap();
+        }
+        
+        if let (true, Some(badge)) = (self.is_verified, self.member_badge.as_deref()) {
+            write!(out, "  {}: {}\n", locale.text("status.badge"), badge).unwrap();
         }
         
         if self.failed_checks != 0 {
-            write!(out, "- failed_checks: {}", self.failed_checks).unwrap();
+            write!(out, "- {}: {}", locale.text("status.failed_checks"), self.failed_checks).unwrap();
         }
         
         write!(out, "```").unwrap();
@@ -530,9 +775,9 @@ impl UserStatus {
             }
         };
         
-        fmt_date("verified member ", &self.last_verified);
-        fmt_date("verified comment", &self.last_channel_verified);
-        fmt_date("checked  fi     ", &self.last_checked);
+        fmt_date(&locale.text("status.verified_member"), &self.last_verified);
+        fmt_date(&locale.text("status.verified_comment"), &self.last_channel_verified);
+        fmt_date(&locale.text("status.checked"), &self.last_checked);
         
         out
     }
@@ -543,24 +788,27 @@ pub async fn get_statuses(
     user_id: u64,
 ) -> Result<Vec<UserStatus>, anyhow::Error> {
     let rows: Vec<(
-        String, i64, Option<String>, Option<String>, String,
+        String, i64, Option<String>, Option<String>, Option<String>, String,
         Option<NaiveDateTime>, Option<NaiveDateTime>, Option<NaiveDateTime>,
         i64,
         Option<bool>,
         Option<bool>,
         Option<sqlx::types::Json<String>>,
+        Option<String>,
     )> = sqlx::query_as(r#"
         SELECT
-            yt_channel_id, yt_channel_n, yt_video_id, yt_comment_id, token,
-            last_verified, last_channel_verified, last_checked,
-            failed_checks,
-            current_timestamp - last_verified < INTERVAL '3 days',
+            u.yt_channel_id, u.yt_channel_n, u.yt_video_id, u.yt_post_id, u.yt_comment_id, u.token,
+            u.last_verified, u.last_channel_verified, u.last_checked,
+            u.failed_checks,
+            current_timestamp - u.last_verified < COALESCE(cs.verify_expiry, INTERVAL '3 days'),
             --current_timestamp - last_channel_verified < INTERVAL '2 months',
-            last_channel_verified IS NOT NULL,
-            (extra->'channel_name')
-        FROM genteib.users
+            u.last_channel_verified IS NOT NULL,
+            (u.extra->'channel_name'),
+            u.member_badge
+        FROM genteib.users u
+        LEFT JOIN genteib.channel_settings cs ON cs.yt_channel_id = u.yt_channel_id
         WHERE
-            discord_id = $1
+            u.discord_id = $1
     "#)
         .bind(to_i(user_id))
         .fetch_all(pool).await
@@ -570,12 +818,13 @@ pub async fn get_statuses(
     
     for row in rows {
         let (
-            yt_channel_id, yt_channel_n, yt_video_id, yt_comment_id, token,
+            yt_channel_id, yt_channel_n, yt_video_id, yt_post_id, yt_comment_id, token,
             last_verified, last_channel_verified, last_checked,
             failed_checks,
             is_verified,
             channel_verified,
             channel_name,
+            member_badge,
         ) = row;
         
         let failed_checks: u64 = failed_checks.try_into()?;
@@ -587,14 +836,16 @@ pub async fn get_statuses(
         let last_checked = last_checked.map(|d| Utc.from_utc_datetime(&d));
         
         let channel_name = channel_name.map(|x| x.0);
+        let target = CommentTarget::from_columns(yt_video_id, yt_post_id);
         
         let user_status = UserStatus {
-            yt_channel_id, yt_channel_n, yt_video_id, yt_comment_id, token,
+            yt_channel_id, yt_channel_n, target, yt_comment_id, token,
             last_verified, last_channel_verified, last_checked,
             failed_checks,
             is_verified,
             channel_verified,
             channel_name,
+            member_badge,
         };
         
         out.push(user_status);
@@ -602,3 +853,499 @@ pub async fn get_statuses(
     
     Ok(out)
 }
+
+pub struct EventRow {
+    created: DateTime<Utc>,
+    yt_channel_id: String,
+    yt_channel_n: i64,
+    target: Option<CommentTarget>,
+    yt_comment_id: Option<String>,
+    user_yt_channel_id: Option<String>,
+    errors: Vec<String>,
+    was_member: bool,
+    is_member: Option<bool>,
+    failure: Option<String>,
+}
+
+impl EventRow {
+    pub fn format_message(&self) -> String {
+        let mut out = String::new();
+        use std::fmt::Write;
+        
+        write!(out, "<t:{}> {}", self.created.timestamp(), self.yt_channel_id).unwrap();
+        if self.yt_channel_n != 0 {
+            write!(out, " '{}", self.yt_channel_n).unwrap();
+        }
+        
+        let member_str = |m: bool| if m { "member" } else { "not member" };
+        match self.is_member {
+            Some(is_member) => write!(out, " {} -> {}", member_str(self.was_member), member_str(is_member)).unwrap(),
+            None => write!(out, " {} -> failed", member_str(self.was_member)).unwrap(),
+        }
+        out.push('\n');
+        
+        if let (Some(target), Some(com)) = (self.target.as_ref(), self.yt_comment_id.as_ref()) {
+            write!(out, "`  `<{}>\n", target.comment_url(com)).unwrap();
+        }
+        if let Some(user_chan) = self.user_yt_channel_id.as_deref() {
+            write!(out, "`  `by <https://www.youtube.com/channel/{}>\n", user_chan).unwrap();
+        }
+        for err in self.errors.iter() {
+            write!(out, "`  `{}\n", err).unwrap();
+        }
+        if let Some(failure) = self.failure.as_deref() {
+            // only the outermost context, the full chain is in the table
+            let first_line = failure.lines().next().unwrap_or("");
+            write!(out, "`  `error: {}\n", first_line).unwrap();
+        }
+        
+        out
+    }
+}
+
+/// Most recent verification events for a user, newest first
+pub async fn get_events(
+    pool: &PgPool,
+    user_id: u64,
+    limit: i64,
+) -> Result<Vec<EventRow>, anyhow::Error> {
+    let rows: Vec<(
+        NaiveDateTime, String, i64, Option<String>, Option<String>, Option<String>, Option<String>,
+        Vec<String>, bool, Option<bool>, Option<String>,
+    )> = sqlx::query_as(r#"
+        SELECT
+            created, yt_channel_id, yt_channel_n, yt_video_id, yt_post_id, yt_comment_id, user_yt_channel_id,
+            errors, was_member, is_member, failure
+        FROM genteib.verification_events
+        WHERE
+            discord_id = $1
+        ORDER BY created DESC
+        LIMIT $2
+    "#)
+        .bind(to_i(user_id))
+        .bind(limit)
+        .fetch_all(pool).await
+        .context("events select")?;
+    
+    let out = rows.into_iter()
+        .map(|(
+            created, yt_channel_id, yt_channel_n, yt_video_id, yt_post_id, yt_comment_id, user_yt_channel_id,
+            errors, was_member, is_member, failure,
+        )| EventRow {
+            created: Utc.from_utc_datetime(&created),
+            yt_channel_id, yt_channel_n,
+            target: CommentTarget::from_columns(yt_video_id, yt_post_id),
+            yt_comment_id, user_yt_channel_id,
+            errors, was_member, is_member, failure,
+        })
+        .collect();
+    
+    Ok(out)
+}
+
+/// Suggestion for channel arguments, see `autocomplete_channel`
+pub struct ChannelChoice {
+    pub yt_channel_id: String,
+    /// `None` for channels mapped in the server that the user has no row for
+    pub yt_channel_n: Option<i64>,
+    pub channel_name: Option<String>,
+}
+
+impl ChannelChoice {
+    /// Argument text that `parse_channel_str` reads back
+    pub fn value(&self) -> String {
+        match self.yt_channel_n {
+            None | Some(0) => self.yt_channel_id.clone(),
+            Some(n) => format!("{}'{}", self.yt_channel_id, n),
+        }
+    }
+    
+    pub fn label(&self, locale: Locale) -> String {
+        let name = self.channel_name.as_deref().unwrap_or(&self.yt_channel_id);
+        match self.yt_channel_n {
+            Some(n) => locale.fmt("reply.channel_choice_slot", &[("channel", &name), ("n", &n)]),
+            None => locale.fmt("reply.channel_choice_server", &[("channel", &name)]),
+        }
+    }
+}
+
+/// The user's own rows, followed by the channels of `guild_channels` they have no row for
+pub async fn get_channel_choices(
+    pool: &PgPool,
+    user_id: u64,
+    guild_channels: &[String],
+) -> Result<Vec<ChannelChoice>, anyhow::Error> {
+    let rows: Vec<(String, i64, Option<String>)> = sqlx::query_as(r#"
+        SELECT yt_channel_id, yt_channel_n, extra->>'channel_name'
+        FROM genteib.users
+        WHERE
+            discord_id = $1
+        ORDER BY yt_channel_id, yt_channel_n
+    "#)
+        .bind(to_i(user_id))
+        .fetch_all(pool).await
+        .context("channel choices select")?;
+    
+    let mut out: Vec<ChannelChoice> = rows.into_iter()
+        .map(|(yt_channel_id, yt_channel_n, channel_name)| ChannelChoice {
+            yt_channel_id,
+            yt_channel_n: Some(yt_channel_n),
+            channel_name,
+        })
+        .collect();
+    
+    let missing: Vec<String> = guild_channels.iter()
+        .filter(|id| !out.iter().any(|x| &x.yt_channel_id == *id))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(out);
+    }
+    
+    // any stored name will do, channel names are the same for every user
+    let names: Vec<(String, Option<String>)> = sqlx::query_as(r#"
+        SELECT
+            c.yt_channel_id,
+            (
+                SELECT u.extra->>'channel_name'
+                FROM genteib.users u
+                WHERE
+                    u.yt_channel_id = c.yt_channel_id AND
+                    u.extra->>'channel_name' IS NOT NULL
+                LIMIT 1
+            )
+        FROM unnest($1::text[]) AS c(yt_channel_id)
+    "#)
+        .bind(missing)
+        .fetch_all(pool).await
+        .context("channel names select")?;
+    
+    out.extend(names.into_iter().map(|(yt_channel_id, channel_name)| ChannelChoice {
+        yt_channel_id,
+        yt_channel_n: None,
+        channel_name,
+    }));
+    
+    Ok(out)
+}
+
+/// Whether the user already proved owning a youtube account with a token comment
+///
+/// Membership comments are accepted without a token from then on, so there is
+/// no need for a new token.
+pub async fn has_verified_channel(pool: &PgPool, user_id: u64) -> Result<bool, anyhow::Error> {
+    let (verified,): (bool,) = sqlx::query_as(r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM genteib.users
+            WHERE
+                discord_id = $1 AND
+                last_channel_verified IS NOT NULL
+        )
+    "#)
+        .bind(to_i(user_id))
+        .fetch_one(pool).await
+        .context("select verified channel")?;
+    Ok(verified)
+}
+
+/// Everything stored about a user, for `my_data`
+///
+/// Rows are exported with all their columns so new columns show up without
+/// changes here.
+pub async fn export_user_data(pool: &PgPool, user_id: u64) -> Result<serde_json::Value, anyhow::Error> {
+    let (users, events, locale): (
+        sqlx::types::Json<serde_json::Value>,
+        sqlx::types::Json<serde_json::Value>,
+        Option<String>,
+    ) = sqlx::query_as(r#"
+        SELECT
+            (
+                SELECT COALESCE(jsonb_agg(to_jsonb(u) ORDER BY u.yt_channel_id, u.yt_channel_n), '[]')
+                FROM genteib.users u
+                WHERE u.discord_id = $1
+            ),
+            (
+                SELECT COALESCE(jsonb_agg(to_jsonb(e) ORDER BY e.created), '[]')
+                FROM genteib.verification_events e
+                WHERE e.discord_id = $1
+            ),
+            (SELECT locale FROM genteib.user_locales WHERE discord_id = $1)
+    "#)
+        .bind(to_i(user_id))
+        .fetch_one(pool).await
+        .context("export user data")?;
+    
+    Ok(serde_json::json!({
+        "discord_id": user_id.to_string(),
+        "exported": Utc::now().to_rfc3339(),
+        "locale": locale,
+        "users": users.0,
+        "verification_events": events.0,
+    }))
+}
+
+/// These run against the database in `DATABASE_URL`, the one the query macros
+/// are checked against, so it needs the migrations applied. Every test uses
+/// its own random discord id and channels.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check_wrapper::{ FakeSource, VideoInfo, MembershipStatus };
+    use crate::util::gen_token;
+    
+    const CHANNEL: &str = "UCmembershipchannel00000";
+    
+    fn init_settings() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let vars = [
+                ("discord_auth", "test"),
+                ("owners", "1"),
+                ("pg_url", "unused"),
+                ("goojf", "test"),
+                ("token_channel", "UCtokenchannel0000000000"),
+                ("token_video", "tokenvideo0"),
+                ("check_backend", "native"),
+            ];
+            for (var, value) in vars {
+                std::env::set_var(var, value);
+            }
+            lazy_static::initialize(&SETTINGS);
+        });
+    }
+    
+    struct TestUser {
+        pool: PgPool,
+        discord_id: u64,
+        /// youtube account of the user
+        user_channel_id: String,
+        token: String,
+        target: CommentTarget,
+        comment_id: String,
+    }
+    
+    impl TestUser {
+        /// A user that set a comment for `CHANNEL`, `None` skips the test when there is no database
+        async fn new() -> Option<Self> {
+            init_settings();
+            let url = match std::env::var("DATABASE_URL") {
+                Ok(url) => url,
+                Err(_) => {
+                    eprintln!("DATABASE_URL is not set, skipping");
+                    return None;
+                }
+            };
+            let pool = PgPool::connect(&url).await.unwrap();
+            
+            let discord_id = rand::random::<u32>() as u64 + 1_000_000;
+            let user = TestUser {
+                pool,
+                discord_id,
+                user_channel_id: format!("UCuser{:018}", discord_id),
+                token: gen_token(),
+                target: CommentTarget::Video("dQw4w9WgXcQ".into()),
+                comment_id: format!("Ugz{}", discord_id),
+            };
+            
+            sqlx::query(r#"
+                INSERT INTO genteib.users
+                    (discord_id, yt_channel_id, yt_channel_n, token, yt_video_id, yt_comment_id)
+                VALUES ($1, $2, 0, $3, $4, $5)
+            "#)
+                .bind(to_i(user.discord_id))
+                .bind(CHANNEL)
+                .bind(&user.token)
+                .bind(user.target.video_id())
+                .bind(&user.comment_id)
+                .execute(&user.pool).await
+                .unwrap();
+            Some(user)
+        }
+        
+        fn member(&self, channel_id: &str, text: &str) -> MembershipStatus {
+            MembershipStatus::Member {
+                channel_id: channel_id.into(),
+                user_channel_id: self.user_channel_id.clone(),
+                text: text.into(),
+                tier: 6,
+                badge: "Member (6 months)".into(),
+            }
+        }
+        
+        fn push(&self, source: &FakeSource, channel_id: &str, result: MembershipStatus) {
+            let video = VideoInfo {
+                channel_name: "channel".into(),
+                channel_id: channel_id.into(),
+            };
+            source.push(&self.target, &self.comment_id, video, result);
+        }
+        
+        async fn verify(&self, source: &FakeSource) -> Result<VerifyResult, anyhow::Error> {
+            update_verification(&self.pool, source, self.discord_id, CHANNEL, 0).await
+        }
+        
+        /// failed_checks, transient_failures, retry_after set, last_verified set, member_tier
+        async fn row(&self) -> (i64, i32, bool, bool, Option<i64>) {
+            sqlx::query_as(r#"
+                SELECT failed_checks, transient_failures, retry_after IS NOT NULL, last_verified IS NOT NULL, member_tier
+                FROM genteib.users
+                WHERE
+                    discord_id = $1 AND
+                    yt_channel_id = $2
+            "#)
+                .bind(to_i(self.discord_id))
+                .bind(CHANNEL)
+                .fetch_one(&self.pool).await
+                .unwrap()
+        }
+    }
+    
+    #[tokio::test]
+    async fn member_with_token() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, user.member(CHANNEL, &format!("hi {}", user.token)));
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(res.is_member);
+        assert!(res.ownership_verified);
+        assert_eq!(res.tier, Some(6));
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        assert_eq!(user.row().await, (0, 0, false, true, Some(6)));
+    }
+    
+    #[tokio::test]
+    async fn comment_on_other_channel() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        let other = "UCotherchannel0000000000";
+        user.push(&source, other, user.member(other, &user.token));
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(!res.is_member);
+        assert_eq!(res.tier, None);
+        assert!(matches!(res.errors[..], [UserError::WrongChannel{ .. }]), "{:?}", res.errors);
+        assert_eq!(user.row().await, (0, 0, false, false, None));
+    }
+    
+    #[tokio::test]
+    async fn not_a_member() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, MembershipStatus::Not {
+            channel_id: CHANNEL.into(),
+            user_channel_id: user.user_channel_id.clone(),
+            text: user.token.clone(),
+        });
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(!res.is_member);
+        // the token still proves the youtube account
+        assert!(res.ownership_verified);
+        assert!(matches!(res.errors[..], [UserError::NotAMember]), "{:?}", res.errors);
+        assert_eq!(user.row().await, (0, 0, false, false, None));
+    }
+    
+    #[tokio::test]
+    async fn member_without_token() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, user.member(CHANNEL, "no token"));
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(!res.is_member);
+        assert!(!res.ownership_verified);
+        assert!(matches!(res.errors[..], [UserError::TokenNotInComment]), "{:?}", res.errors);
+    }
+    
+    #[tokio::test]
+    async fn token_not_needed_once_ownership_is_proven() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, user.member(CHANNEL, &user.token));
+        user.push(&source, CHANNEL, user.member(CHANNEL, "edited, no token"));
+        
+        assert!(user.verify(&source).await.unwrap().ownership_verified);
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(res.is_member);
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+    }
+    
+    #[tokio::test]
+    async fn transient_error_then_retry() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        source.push_transient(&user.target, &user.comment_id, "youtube is down");
+        user.push(&source, CHANNEL, user.member(CHANNEL, &user.token));
+        
+        let err = user.verify(&source).await.unwrap_err();
+        let err = err.downcast_ref::<CheckError>().expect("check error");
+        assert!(err.is_transient());
+        // does not count against the user, backs off instead
+        assert_eq!(user.row().await, (0, 1, true, false, None));
+        
+        let res = user.verify(&source).await.unwrap();
+        assert!(res.is_member);
+        assert_eq!(user.row().await, (0, 0, false, true, Some(6)));
+    }
+    
+    #[tokio::test]
+    async fn over_paired_youtube_account() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, user.member(CHANNEL, &user.token));
+        
+        // other discord accounts already paired with the same youtube account
+        for other in 1..=4 {
+            sqlx::query(r#"
+                INSERT INTO genteib.users
+                    (discord_id, yt_channel_id, yt_channel_n, token, user_yt_channel_id)
+                VALUES ($1, $2, 0, $3, $4)
+            "#)
+                .bind(to_i(user.discord_id + other * 10_000_000_000))
+                .bind(CHANNEL)
+                .bind(gen_token())
+                .bind(&user.user_channel_id)
+                .execute(&user.pool).await
+                .unwrap();
+        }
+        
+        let res = user.verify(&source).await.unwrap();
+        // the membership still counts, the youtube account is not linked
+        assert!(res.is_member);
+        assert!(!res.ownership_verified);
+        assert!(matches!(res.errors[..], [UserError::OverPairedDiscordId]), "{:?}", res.errors);
+    }
+    
+    #[tokio::test]
+    async fn too_many_failures() {
+        let user = match TestUser::new().await { Some(user) => user, None => return };
+        let source = FakeSource::new();
+        user.push(&source, CHANNEL, user.member(CHANNEL, &user.token));
+        
+        sqlx::query(r#"
+            UPDATE genteib.users
+                SET failed_checks = 100
+                WHERE discord_id = $1
+        "#)
+            .bind(to_i(user.discord_id))
+            .execute(&user.pool).await
+            .unwrap();
+        
+        let err = user.verify(&source).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<UserError>(), Some(UserError::TooManyFailures)));
+        
+        // the row has to leave pending_verifications until the next recheck
+        let (checked,): (bool,) = sqlx::query_as(r#"
+            SELECT last_checked IS NOT NULL
+            FROM genteib.users
+            WHERE discord_id = $1
+        "#)
+            .bind(to_i(user.discord_id))
+            .fetch_one(&user.pool).await
+            .unwrap();
+        assert!(checked);
+    }
+}
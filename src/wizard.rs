@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use anyhow::anyhow;
+use poise::serenity_prelude as serenity;
+use tracing::error;
+
+use crate::{ Context, Error, new_token_inner, parse_comment_link, set_comment_inner, ctx_locale };
+use crate::locale::Locale;
+use crate::settings::SETTINGS;
+use crate::user_error::UserError;
+use crate::util;
+use crate::verification;
+
+/// How long the wizard waits for each click or link before giving up
+const STEP_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const START_BUTTON: &str = "wizard_start";
+
+/// Users with a wizard waiting for their replies, counted per running wizard
+///
+/// `check_prefix` does not parse their DMs as commands, so a pasted link is
+/// only seen by the wizard.
+#[derive(Default)]
+pub struct ActiveWizards(Mutex<HashMap<serenity::UserId, usize>>);
+
+impl ActiveWizards {
+    pub fn contains(&self, user_id: serenity::UserId) -> bool {
+        self.0.lock().unwrap().contains_key(&user_id)
+    }
+    
+    fn enter(&self, user_id: serenity::UserId) -> ActiveGuard<'_> {
+        *self.0.lock().unwrap().entry(user_id).or_insert(0) += 1;
+        ActiveGuard{ wizards: self, user_id }
+    }
+}
+
+/// Removes the user from `ActiveWizards` when the wizard ends, also on errors
+struct ActiveGuard<'a> {
+    wizards: &'a ActiveWizards,
+    user_id: serenity::UserId,
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        let mut active = self.wizards.0.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+/// What the wizard message currently asks for, steps of the guide in order
+enum Step {
+    /// waiting for the start button, `new_token` runs when it is pressed
+    Start,
+    /// waiting for a reply with the link to the comment with the token, guide steps 3-5
+    TokenComment {
+        token: String,
+    },
+    /// ownership verified, waiting for a reply with a comment on the member's channel, guide steps 6-8
+    MemberComment,
+    /// a link is being checked with `set_comment_inner`
+    Checking,
+    Done {
+        channel_name: String,
+    },
+    TimedOut,
+}
+
+impl Step {
+    fn wants_link(&self) -> bool {
+        matches!(self, Step::TokenComment{ .. } | Step::MemberComment)
+    }
+}
+
+enum Event {
+    Button(Arc<serenity::MessageComponentInteraction>),
+    /// a message the author sent in the DM channel of the wizard
+    Reply(Arc<serenity::Message>),
+}
+
+fn fill_embed<'a>(e: &'a mut serenity::CreateEmbed, locale: Locale, step: &Step, note: Option<&str>) -> &'a mut serenity::CreateEmbed {
+    let text = match step {
+        Step::Start => locale.text("wizard.start"),
+        Step::TokenComment{ token } => locale.fmt("wizard.token_comment", &[
+            ("token", token),
+            ("video_id", &SETTINGS.youtube.token_video),
+        ]),
+        Step::MemberComment => locale.text("wizard.member_comment"),
+        Step::Checking => locale.text("wizard.checking"),
+        Step::Done{ channel_name } => locale.fmt("wizard.done", &[("channel", channel_name)]),
+        Step::TimedOut => locale.text("wizard.timed_out"),
+    };
+    e.title(locale.text("wizard.title")).description(text);
+    if let Some(note) = note {
+        e.field(locale.text("wizard.problem"), note, false);
+    }
+    e
+}
+
+/// Only the first step has a button, links are sent as replies
+fn fill_buttons<'a>(c: &'a mut serenity::CreateComponents, locale: Locale, step: &Step) -> &'a mut serenity::CreateComponents {
+    if !matches!(step, Step::Start) {
+        return c;
+    }
+    c.create_action_row(|row| {
+        row.create_button(|b| {
+            b
+                .custom_id(START_BUTTON)
+                .label(locale.text("wizard.start_button"))
+                .style(serenity::ButtonStyle::Primary)
+        })
+    })
+}
+
+/// Button presses on the wizard message and messages from the author in its DM channel
+async fn next_event(ctx: Context<'_>, msg: &serenity::Message) -> Option<Event> {
+    let user_id = ctx.author().id;
+    tokio::select! {
+        press = msg.await_component_interaction(ctx.discord()).author_id(user_id).timeout(STEP_TIMEOUT) => press.map(Event::Button),
+        reply = msg.channel_id.await_reply(ctx.discord()).author_id(user_id).timeout(STEP_TIMEOUT) => reply.map(Event::Reply),
+    }
+}
+
+/// Shown on the wizard message instead of going through `error_handler`
+fn error_note(err: &Error, locale: Locale) -> String {
+    match err.downcast_ref::<UserError>() {
+        Some(user_error) => user_error.render(locale),
+        None => {
+            let err_uuid = util::gen_uuid();
+            let err_uuid = &err_uuid[..17];
+            error!(err_uuid, error = ?err, "wizard error");
+            format!("{} ({})", locale.text("severity.error"), err_uuid)
+        }
+    }
+}
+
+/// Step after the start button
+///
+/// Users that already proved owning a youtube account go straight to the
+/// membership comment, a new token would reset their verified row.
+async fn start(ctx: Context<'_>) -> Result<Step, Error> {
+    if verification::has_verified_channel(&ctx.data().pool, ctx.author().id.0).await? {
+        return Ok(Step::MemberComment);
+    }
+    let token = new_token_inner(ctx, &ctx.data().config.token_channel).await?;
+    Ok(Step::TokenComment{ token })
+}
+
+/// Set the link for `step` like `set_comment` and return the step after it
+async fn submit_link(ctx: Context<'_>, step: &Step, link: &str) -> Result<Step, Error> {
+    let ref token_channel = ctx.data().config.token_channel;
+    let link = parse_comment_link(ctx, link).await?;
+    let channel_id = link.channel_id;
+    
+    match step {
+        Step::TokenComment{ .. } => {
+            if &channel_id != token_channel {
+                let err = anyhow!("wizard token comment on {}", channel_id)
+                    .context(UserError::WrongChannel{ correct: token_channel.clone(), actual: channel_id });
+                return Err(err);
+            }
+            let res = set_comment_inner(ctx, &channel_id, 0, &link.target, &link.comment_id, link.prefetched).await?;
+            if !res.ownership_verified {
+                let user_error = res.errors.into_iter().next().unwrap_or(UserError::TokenNotInComment);
+                return Err(anyhow!("wizard token comment not verified").context(user_error));
+            }
+            Ok(Step::MemberComment)
+        }
+        Step::MemberComment => {
+            if &channel_id == token_channel {
+                return Err(anyhow!("wizard membership comment on token channel").context(UserError::MembershipOnTokenChannel));
+            }
+            let res = set_comment_inner(ctx, &channel_id, 0, &link.target, &link.comment_id, link.prefetched).await?;
+            if !res.is_member {
+                let user_error = res.errors.into_iter().next().unwrap_or(UserError::NotAMember);
+                return Err(anyhow!("wizard membership comment not verified").context(user_error));
+            }
+            Ok(Step::Done{ channel_name: res.channel_name })
+        }
+        _ => Err(anyhow!("wizard link submitted outside a link step")),
+    }
+}
+
+/// Walk the author through the guide in a DM, a button starts it and the comment links are sent as replies
+pub async fn run(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx_locale(ctx).await;
+    let mut step = Step::Start;
+    let mut note: Option<String> = None;
+    let _active = ctx.data().active_wizards.enter(ctx.author().id);
+    
+    let mut msg = ctx.author().direct_message(ctx.discord(), |m| {
+        m
+            .embed(|e| fill_embed(e, locale, &step, None))
+            .components(|c| fill_buttons(c, locale, &step))
+    }).await?;
+    poise::say_reply(ctx, locale.text("wizard.check_dms")).await?;
+    
+    loop {
+        let event = match next_event(ctx, &msg).await {
+            Some(event) => event,
+            None => {
+                step = Step::TimedOut;
+                msg.edit(ctx.discord(), |m| {
+                    m
+                        .embed(|e| fill_embed(e, locale, &step, None))
+                        .components(|c| fill_buttons(c, locale, &step))
+                }).await?;
+                return Ok(());
+            }
+        };
+        
+        match event {
+            Event::Button(press) if press.data.custom_id == START_BUTTON && matches!(step, Step::Start) => {
+                match start(ctx).await {
+                    Ok(next) => {
+                        step = next;
+                        note = None;
+                    }
+                    Err(err) => note = Some(error_note(&err, locale)),
+                }
+                press.create_interaction_response(ctx.discord(), |r| {
+                    r
+                        .kind(serenity::InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|d| {
+                            d
+                                .embed(|e| fill_embed(e, locale, &step, note.as_deref()))
+                                .components(|c| fill_buttons(c, locale, &step))
+                        })
+                }).await?;
+            }
+            // a stale button, acknowledge it so discord does not show a failure
+            Event::Button(press) => {
+                press.create_interaction_response(ctx.discord(), |r| {
+                    r.kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+                }).await?;
+            }
+            Event::Reply(reply) if step.wants_link() => {
+                let prev = std::mem::replace(&mut step, Step::Checking);
+                
+                // the check can take a while, show that it is running first
+                msg.edit(ctx.discord(), |m| {
+                    m
+                        .embed(|e| fill_embed(e, locale, &step, None))
+                        .components(|c| fill_buttons(c, locale, &step))
+                }).await?;
+                
+                match submit_link(ctx, &prev, reply.content.trim()).await {
+                    Ok(next) => {
+                        step = next;
+                        note = None;
+                    }
+                    Err(err) => {
+                        step = prev;
+                        note = Some(error_note(&err, locale));
+                    }
+                }
+                msg.edit(ctx.discord(), |m| {
+                    m
+                        .embed(|e| fill_embed(e, locale, &step, note.as_deref()))
+                        .components(|c| fill_buttons(c, locale, &step))
+                }).await?;
+                
+                if let Step::Done{ .. } = step {
+                    return Ok(());
+                }
+            }
+            // messages before the start button is pressed are not links to check
+            Event::Reply(_) => (),
+        }
+    }
+}
@@ -1,66 +1,171 @@
 
 use std::sync::Arc;
-use std::sync::RwLock;
-use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
 use lazy_static::lazy_static;
+use lru::LruCache;
 use reqwest::Url;
 use scraper::{ Html, Selector };
-use anyhow::anyhow;
-use crate::check_wrapper::RATE_LIMIT;
+use anyhow::{ anyhow, Context as _ };
+use chrono::Utc;
+use sqlx::PgPool;
+use crate::check_wrapper::wait_rate_limit;
+use crate::url_parse::{ self, ChannelRef };
 
-use crate::GOOJF;
+use crate::settings::SETTINGS;
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/79.0.3945.130 Safari/537.36";
 
 lazy_static!{
-    static ref CHANNEL_CACHE: Arc<RwLock<HashMap<String, String>>> = {
-        let map = HashMap::new();
-        Arc::new(RwLock::new(map))
+    /// (table, key) -> (channel id, when it was cached), in front of the lookup tables
+    static ref CHANNEL_CACHE: Mutex<LruCache<(&'static str, String), (String, Instant)>> = {
+        Mutex::new(LruCache::new(SETTINGS.channel_cache.size))
     };
 }
 
-pub async fn get_channel_id(video_url: &str) -> Result<String, anyhow::Error> {
-    {
-        let cache = CHANNEL_CACHE.read()
-            .map_err(|err| {
-                anyhow!("could not aquire rwlock lock {:?}", err)
-            })?;
-        if let Some(chan_id) = cache.get(video_url) {
-            return Ok(chan_id.into());
+/// Table caching page -> channel id lookups
+struct LookupTable {
+    table: &'static str,
+    key_column: &'static str,
+}
+
+const VIDEO_CHANNELS: LookupTable = LookupTable {
+    table: "genteib.video_channels",
+    key_column: "yt_video_id",
+};
+
+const CHANNEL_ALIASES: LookupTable = LookupTable {
+    table: "genteib.channel_aliases",
+    key_column: "alias",
+};
+
+fn cache_get(lookup: &LookupTable, key: &str, ttl: Duration) -> Option<String> {
+    let mut cache = CHANNEL_CACHE.lock().unwrap();
+    let cache_key = (lookup.table, key.to_string());
+    match cache.get(&cache_key) {
+        Some((channel_id, fetched)) if fetched.elapsed() < ttl => Some(channel_id.clone()),
+        Some(_) => {
+            cache.pop(&cache_key);
+            None
         }
+        None => None,
     }
+}
+
+fn cache_put(lookup: &LookupTable, key: &str, channel_id: &str) {
+    CHANNEL_CACHE.lock().unwrap()
+        .put((lookup.table, key.to_string()), (channel_id.to_string(), Instant::now()));
+}
+
+/// Channel id read from `page_url` with `parse`, from the in-process cache, the lookup table or the page
+async fn cached_channel_id(
+    pool: &PgPool,
+    lookup: &LookupTable,
+    key: &str,
+    page_url: &str,
+    parse: fn(&Html) -> Result<String, anyhow::Error>,
+) -> Result<String, anyhow::Error> {
+    let ttl = Duration::from_secs(SETTINGS.channel_cache.ttl);
+    
+    if let Some(channel_id) = cache_get(lookup, key, ttl) {
+        return Ok(channel_id);
+    }
+    
+    let cutoff = Utc::now() - chrono::Duration::from_std(ttl)?;
+    let row: Option<(String,)> = sqlx::query_as(&format!(r#"
+        SELECT yt_channel_id
+        FROM {table}
+        WHERE
+            {key} = $1 AND
+            fetched > $2
+    "#, table = lookup.table, key = lookup.key_column))
+        .bind(key)
+        .bind(cutoff.naive_utc())
+        .fetch_optional(pool).await
+        .with_context(|| format!("select cached channel from {}", lookup.table))?;
     
-    RATE_LIMIT.until_ready().await;
+    if let Some((channel_id,)) = row {
+        cache_put(lookup, key, &channel_id);
+        return Ok(channel_id);
+    }
     
-    // let body = reqwest::get(video_url)
-    //     .await?
-    //     .error_for_status()?
-    //     .text().await?;
+    wait_rate_limit(page_url).await;
     
+    let client = build_client()?;
+    
+    let body = fetch_page(&client, page_url).await?;
+    
+    // println!("{}", video_url);
+    // std::fs::write("yt_html_b.html", &body).expect("Unable to write file");
+    
+    let channel_id = {
+        let document = Html::parse_document(&body);
+        parse(&document)?
+    };
+    
+    sqlx::query(&format!(r#"
+        INSERT INTO {table} ({key}, yt_channel_id, fetched)
+        VALUES ($1, $2, $3)
+        ON CONFLICT ({key}) DO UPDATE
+            SET yt_channel_id = EXCLUDED.yt_channel_id, fetched = EXCLUDED.fetched
+    "#, table = lookup.table, key = lookup.key_column))
+        .bind(key)
+        .bind(&channel_id)
+        .bind(Utc::now().naive_utc())
+        .execute(pool).await
+        .with_context(|| format!("store channel in {}", lookup.table))?;
+    
+    cache_put(lookup, key, &channel_id);
+    
+    Ok(channel_id)
+}
+
+/// Channel owning the video
+pub async fn get_channel_id(pool: &PgPool, video_id: &str) -> Result<String, anyhow::Error> {
+    let video_url = format!("https://www.youtube.com/watch?v={}", video_id);
+    cached_channel_id(pool, &VIDEO_CHANNELS, video_id, &video_url, channel_id_meta).await
+}
+
+/// Canonical UC id for a channel url, fetching the channel page for handles and custom urls
+pub async fn resolve_channel(pool: &PgPool, channel: &ChannelRef) -> Result<String, anyhow::Error> {
+    match channel {
+        ChannelRef::Id(id) => Ok(id.clone()),
+        _ => cached_channel_id(pool, &CHANNEL_ALIASES, &channel.alias(), &channel.url(), channel_page_id).await,
+    }
+}
+
+/// Client with the cookies needed to get past the consent and captcha pages
+pub fn build_client() -> Result<reqwest::Client, anyhow::Error> {
     // let mut headers = header::HeaderMap::new();
     // headers.insert("X-MY-HEADER", header::HeaderValue::from_static("value"));
     
     let jar = reqwest::cookie::Jar::default();
     let url = "https://www.youtube.com".parse::<Url>().unwrap();
     // let url = "https://youtube.com".parse::<Url>().unwrap();
-    jar.add_cookie_str(&format!("goojf={}", &*GOOJF), &url);
+    jar.add_cookie_str(&format!("goojf={}", SETTINGS.youtube.goojf.expose()), &url);
     jar.add_cookie_str("CONSENT=YES+cb; Domain=.youtube.com", &url);
     
     let client = reqwest::Client::builder()
         .cookie_provider(Arc::new(jar))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/79.0.3945.130 Safari/537.36")
+        .user_agent(USER_AGENT)
         .build()?;
     
+    Ok(client)
+}
+
+pub async fn fetch_page(client: &reqwest::Client, url: &str) -> Result<String, anyhow::Error> {
     let body = client
-        .get(video_url)
+        .get(url)
         // .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/79.0.3945.130 Safari/537.36")
         .send().await?
         .error_for_status()?
         .text().await?;
     
-    // println!("{}", video_url);
-    // std::fs::write("yt_html_b.html", &body).expect("Unable to write file");
-    
-    let document = Html::parse_document(&body);
-    
+    Ok(body)
+}
+
+/// Channel id of the page owner from `<meta itemprop="channelId">`
+pub fn channel_id_meta(document: &Html) -> Result<String, anyhow::Error> {
     let selector = Selector::parse(r#"meta[itemprop="channelId"]"#).unwrap();
     
     let elem = document.select(&selector).next().ok_or_else(|| {
@@ -71,16 +176,36 @@ pub async fn get_channel_id(video_url: &str) -> Result<String, anyhow::Error> {
         anyhow!("no content attribute in element")
     })?;
     
-    {
-        let mut cache = CHANNEL_CACHE.write()
-            .map_err(|err| {
-                anyhow!("could not aquire rwlock lock {:?}", err)
-            })?;
-        cache.insert(video_url.into(), channel_id.into());
-        // if let Some(chan_id) = cache.insert(video_url, channel_id.into()) {
-            // return Ok(chan_id.into());
-        // }
-    }
-    
     Ok(channel_id.into())
 }
+
+/// Channel id of a channel page
+///
+/// Channel pages only carry `<meta itemprop="identifier">` in some layouts.
+/// Watch pages have identifiers that are not channel ids, so this is only
+/// for channel urls.
+pub fn channel_page_id(document: &Html) -> Result<String, anyhow::Error> {
+    channel_id_meta(document).or_else(|err| {
+        let selector = Selector::parse(r#"meta[itemprop="identifier"]"#).unwrap();
+        document.select(&selector).next()
+            .and_then(|elem| elem.value().attr("content"))
+            .filter(|channel_id| url_parse::is_channel_id(channel_id))
+            .map(|channel_id| channel_id.to_string())
+            .ok_or(err)
+    })
+}
+
+/// Channel name of the video author from `<span itemprop="author"><link itemprop="name">`
+pub fn channel_name_meta(document: &Html) -> Result<String, anyhow::Error> {
+    let selector = Selector::parse(r#"span[itemprop="author"] link[itemprop="name"]"#).unwrap();
+    
+    let elem = document.select(&selector).next().ok_or_else(|| {
+        anyhow!("did not find channel name element in youtube response")
+    })?.value();
+    
+    let channel_name = elem.attr("content").ok_or_else(|| {
+        anyhow!("no content attribute in element")
+    })?;
+    
+    Ok(channel_name.into())
+}
@@ -0,0 +1,274 @@
+// native port of comment_scrapper/downloader.py
+// https://github.com/egbertbouman/youtube-comment-downloader/blob/master/youtube_comment_downloader/downloader.py
+
+use std::time::Duration;
+use anyhow::{ anyhow, Context as _ };
+use lazy_static::lazy_static;
+use regex::Regex;
+use scraper::Html;
+use serde_json::Value;
+
+use crate::check_wrapper::{ wait_rate_limit, CheckError, CommentTarget, VideoInfo, MembershipStatus, Member, Not, NotFound, badge_tier };
+use crate::youtube_req;
+
+lazy_static! {
+    static ref YT_CFG_RE: Regex = Regex::new(r#"ytcfg\.set\s*\(\s*(\{.+?\})\s*\)\s*;"#).unwrap();
+    static ref YT_INITIAL_DATA_RE: Regex = Regex::new(r#"(?:window\s*\[\s*["']ytInitialData["']\s*\]|ytInitialData)\s*=\s*(\{.+?\})\s*;\s*(?:var\s+meta|</script|\n)"#).unwrap();
+    /// Set on watch pages of removed or private videos
+    static ref PLAYABILITY_ERROR_RE: Regex = Regex::new(r#""playabilityStatus"\s*:\s*\{\s*"status"\s*:\s*"ERROR""#).unwrap();
+}
+
+/// Everything needed from the watch or post page before following continuations
+struct WatchPage {
+    ytcfg: Value,
+    initial_data: Value,
+    channel_id: String,
+    channel_name: String,
+}
+
+fn regex_search<'a>(text: &'a str, re: &Regex) -> Option<&'a str> {
+    re.captures(text).and_then(|c| c.get(1)).map(|m| m.as_str())
+}
+
+/// All values stored under `search_key` anywhere in `partial`
+pub fn search_dict<'a>(partial: &'a Value, search_key: &str) -> Vec<&'a Value> {
+    let mut out = Vec::new();
+    let mut stack = vec![partial];
+    while let Some(current) = stack.pop() {
+        match current {
+            Value::Object(map) => {
+                for (key, value) in map.iter() {
+                    if key == search_key {
+                        out.push(value);
+                    } else {
+                        stack.push(value);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                stack.extend(values.iter());
+            }
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Author of a community post page from its `backstagePostRenderer`
+fn post_author(initial_data: &Value) -> Result<(String, String), anyhow::Error> {
+    let post = search_dict(initial_data, "backstagePostRenderer").into_iter().next()
+        .ok_or_else(|| anyhow!("no backstagePostRenderer on post page"))?;
+    
+    let channel_id = post["authorEndpoint"]["browseEndpoint"]["browseId"].as_str()
+        .ok_or_else(|| anyhow!("post has no author channel id"))?;
+    let channel_name: String = post["authorText"]["runs"].as_array()
+        .map(|runs| runs.iter().filter_map(|r| r["text"].as_str()).collect())
+        .unwrap_or_default();
+    
+    Ok((channel_id.into(), channel_name))
+}
+
+// Html is not Send so parsing is kept out of the async fns
+fn parse_watch_page(html: &str, target: &CommentTarget) -> Result<WatchPage, anyhow::Error> {
+    let ytcfg_text = regex_search(html, &YT_CFG_RE)
+        .ok_or_else(|| anyhow!("unable to extract ytcfg"))?;
+    let ytcfg: Value = serde_json::from_str(ytcfg_text).context("parse ytcfg")?;
+    
+    let data_text = regex_search(html, &YT_INITIAL_DATA_RE)
+        .ok_or_else(|| anyhow!("unable to extract ytInitialData"))?;
+    let initial_data: Value = serde_json::from_str(data_text).context("parse ytInitialData")?;
+    
+    let (channel_id, channel_name) = match target {
+        CommentTarget::Video(_) => {
+            let document = Html::parse_document(html);
+            (youtube_req::channel_id_meta(&document)?, youtube_req::channel_name_meta(&document)?)
+        }
+        CommentTarget::Post(_) => post_author(&initial_data)?,
+    };
+    
+    Ok(WatchPage {
+        ytcfg,
+        initial_data,
+        channel_id,
+        channel_name,
+    })
+}
+
+async fn ajax_request(client: &reqwest::Client, endpoint: &Value, ytcfg: &Value) -> Result<Option<Value>, anyhow::Error> {
+    const RETRIES: usize = 5;
+    
+    let api_url = endpoint["commandMetadata"]["webCommandMetadata"]["apiUrl"].as_str()
+        .ok_or_else(|| anyhow!("continuation has no apiUrl"))?;
+    let url = format!("https://www.youtube.com{}", api_url);
+    
+    let api_key = ytcfg["INNERTUBE_API_KEY"].as_str()
+        .ok_or_else(|| anyhow!("ytcfg has no INNERTUBE_API_KEY"))?;
+    
+    // badge tooltips are parsed as english, see badge_tier
+    let mut context = ytcfg["INNERTUBE_CONTEXT"].clone();
+    context["client"]["hl"] = "en".into();
+    
+    let data = serde_json::json!({
+        "context": context,
+        "continuation": endpoint["continuationCommand"]["token"],
+    });
+    
+    for _ in 0..RETRIES {
+        wait_rate_limit(&url).await;
+        
+        let response = client
+            .post(&url)
+            .query(&[("key", api_key)])
+            .json(&data)
+            .send().await?;
+        
+        let status = response.status().as_u16();
+        if status == 200 {
+            return Ok(Some(response.json().await?));
+        }
+        if status == 403 || status == 413 {
+            return Ok(None);
+        }
+        tokio::time::sleep(Duration::from_secs(20)).await;
+    }
+    
+    Err(anyhow!("continuation request failed after {} retries", RETRIES))
+}
+
+fn comment_status(comment: &Value, channel_id: &str) -> MembershipStatus {
+    let text: String = comment["contentText"]["runs"].as_array()
+        .map(|runs| runs.iter().filter_map(|r| r["text"].as_str()).collect())
+        .unwrap_or_default();
+    
+    let user_channel_id = comment["authorEndpoint"]["browseEndpoint"]["browseId"].as_str()
+        .unwrap_or("")
+        .to_string();
+    
+    let is_member = search_dict(comment, "customBadge").into_iter().next()
+        .and_then(|badge| badge["thumbnails"][0]["url"].as_str())
+        .map(|url| !url.is_empty())
+        .unwrap_or(false);
+    
+    let badge = search_dict(comment, "sponsorCommentBadgeRenderer").into_iter().next()
+        .and_then(|renderer| renderer["tooltip"].as_str())
+        .unwrap_or("")
+        .to_string();
+    
+    let channel_id = channel_id.to_string();
+    if is_member {
+        let tier = badge_tier(&badge);
+        Member{ channel_id, user_channel_id, text, tier, badge }
+    } else {
+        Not{ channel_id, user_channel_id, text }
+    }
+}
+
+/// Comment section of a post page, which also has a section holding the post itself
+fn post_comment_section(initial_data: &Value) -> Option<&Value> {
+    let sections = search_dict(initial_data, "itemSectionRenderer");
+    sections.iter()
+        .find(|section| section["sectionIdentifier"].as_str() == Some("comment-item-section"))
+        .or_else(|| sections.iter().find(|section| !search_dict(section, "continuationItemRenderer").is_empty()))
+        .copied()
+}
+
+/// The video or post is gone, as opposed to a page we failed to parse
+fn page_unavailable(html: &str, target: &CommentTarget) -> bool {
+    match target {
+        CommentTarget::Video(_) => PLAYABILITY_ERROR_RE.is_match(html),
+        CommentTarget::Post(_) => !html.contains("\"backstagePostRenderer\""),
+    }
+}
+
+/// Removed videos and posts are the user's to fix, anything else going wrong is transient
+pub async fn fetch_comment(target: &CommentTarget, comment_id: &str) -> Result<(VideoInfo, MembershipStatus), CheckError> {
+    let client = youtube_req::build_client().map_err(CheckError::Transient)?;
+    
+    // with lc set the linked comment is shown first
+    let url = target.comment_url(comment_id);
+    let html = youtube_req::fetch_page(&client, &url).await.map_err(CheckError::classify)?;
+    
+    if page_unavailable(&html, target) {
+        return Err(CheckError::Definitive(anyhow!("{} is unavailable", target)));
+    }
+    
+    let WatchPage { ytcfg, initial_data, channel_id, channel_name } = parse_watch_page(&html, target)
+        .map_err(CheckError::Transient)?;
+    
+    let video_info = VideoInfo {
+        channel_id: channel_id.clone(),
+        channel_name,
+    };
+    
+    let section = match target {
+        CommentTarget::Video(_) => search_dict(&initial_data, "itemSectionRenderer").into_iter().next(),
+        CommentTarget::Post(_) => post_comment_section(&initial_data),
+    };
+    let renderer = section
+        .and_then(|section| search_dict(section, "continuationItemRenderer").into_iter().next());
+    let renderer = match renderer {
+        Some(renderer) => renderer,
+        // comments disabled?
+        None => return Ok((video_info, NotFound)),
+    };
+    
+    let status = find_comment(&client, &ytcfg, renderer, comment_id, &channel_id).await
+        .map_err(CheckError::classify)?;
+    Ok((video_info, status))
+}
+
+/// Follow comment continuations from `renderer` until `comment_id` shows up
+async fn find_comment(
+    client: &reqwest::Client,
+    ytcfg: &Value,
+    renderer: &Value,
+    comment_id: &str,
+    channel_id: &str,
+) -> Result<MembershipStatus, anyhow::Error> {
+    let mut continuations: Vec<Value> = vec![renderer["continuationEndpoint"].clone()];
+    while let Some(continuation) = continuations.pop() {
+        let response = match ajax_request(client, &continuation, ytcfg).await? {
+            Some(response) => response,
+            None => break,
+        };
+        
+        if let Some(err) = search_dict(&response, "externalErrorMessage").into_iter().next() {
+            return Err(anyhow!("Error returned from server: {}", err));
+        }
+        
+        let mut actions = search_dict(&response, "reloadContinuationItemsCommand");
+        actions.extend(search_dict(&response, "appendContinuationItemsAction"));
+        for action in actions {
+            let target_id = action["targetId"].as_str().unwrap_or("");
+            let items = match action["continuationItems"].as_array() {
+                Some(items) => items,
+                None => continue,
+            };
+            for item in items {
+                if target_id == "comments-section" {
+                    // process continuations for comments and replies
+                    let mut endpoints: Vec<Value> = search_dict(item, "continuationEndpoint")
+                        .into_iter().cloned().collect();
+                    endpoints.extend(continuations.drain(..));
+                    continuations = endpoints;
+                }
+                if target_id.starts_with("comment-replies-item") && item.get("continuationItemRenderer").is_some() {
+                    // process the 'Show more replies' button
+                    if let Some(button) = search_dict(item, "buttonRenderer").into_iter().next() {
+                        continuations.push(button["command"].clone());
+                    }
+                }
+            }
+        }
+        
+        let found = search_dict(&response, "commentRenderer").into_iter()
+            .find(|comment| comment["commentId"].as_str() == Some(comment_id));
+        if let Some(comment) = found {
+            return Ok(comment_status(comment, channel_id));
+        }
+        
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    
+    Ok(NotFound)
+}